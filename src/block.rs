@@ -1,48 +1,54 @@
-use macroquad::rand::gen_range;
-use macroquad::prelude::*;
-use crate::COLORS;
-use crate::Game;
+use macroquad::math::Vec2;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum BlockShape {
     I, O, T, J, L, S, Z,
 }
 
-#[derive(Copy, Clone)]
+impl BlockShape {
+    pub const ALL: [BlockShape; 7] = [
+        BlockShape::I,
+        BlockShape::O,
+        BlockShape::T,
+        BlockShape::J,
+        BlockShape::L,
+        BlockShape::S,
+        BlockShape::Z,
+    ];
+}
+
+/// A piece as the engine sees it. How it's drawn is up to the frontend.
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Block {
+    /// Where the block is on the board, in cells.
     pub position: Vec2,
-    pub render_position: Vec2,
     pub rotation: u8,
     pub block_shape: BlockShape,
     pub gravity_timer: f32,
-    pub movement_timer: f32,
+    /// Frames left on the ground before the block locks.
+    pub lock_timer: f32,
+    pub lock_resets: u32,
+    /// The lowest row the block has reached, so falling further can reset
+    /// the lock timer.
+    pub lowest_y: f32,
 }
 
-impl Default for Block {
-    fn default() -> Block {
+impl Block {
+    /// A block lying flat, in SRS state 0, the way every piece spawns.
+    pub fn new(block_shape: BlockShape, position: Vec2) -> Block {
         Block {
-            position: vec2(13.0, 1.0),
-            render_position: vec2(13.0 * 16.0, 16.0),
-            rotation: 0,
-            block_shape: match gen_range(0, 7) {
-                0 => BlockShape::I,
-                1 => BlockShape::O,
-                2 => BlockShape::T,
-                3 => BlockShape::J,
-                4 => BlockShape::L,
-                5 => BlockShape::S,
-                6 => BlockShape::Z,
-                _ => BlockShape::I,
-            },
+            position,
+            rotation: Block::flat_rotation(block_shape),
+            block_shape,
             gravity_timer: 45.0,
-            movement_timer: 0.0,
+            lock_timer: 30.0,
+            lock_resets: 0,
+            lowest_y: 0.0,
         }
     }
-}
 
-impl Block {
     pub fn get_shape(&self) -> [[u8; 4]; 4] {
-        return match self.block_shape {
+        match self.block_shape {
             BlockShape::I => {
                 match self.rotation {
                     0 => {
@@ -82,7 +88,7 @@ impl Block {
             },
             BlockShape::O => {
                 match self.rotation {
-                    0 | 1 | 2 | 3 => {
+                    0..=3 => {
                         [
                             [0, 0, 0, 0],
                             [0, 2, 2, 0],
@@ -281,23 +287,78 @@ impl Block {
         }
     }
 
-    pub fn lerp_position(&mut self) {
-        self.render_position = self.render_position.lerp(self.position * 16.0, 0.5);
+    /// The SRS orientation `rotation` draws (0 = spawn, 1 = R, 2 = 2, 3 = L).
+    /// Rotation always steps clockwise through the tables below, but I and J
+    /// start upright in the L orientation and L starts in the R one.
+    /// The rotation of `block_shape`'s shape table that's SRS state 0, the
+    /// inverse of `srs_state`.
+    fn flat_rotation(block_shape: BlockShape) -> u8 {
+        match block_shape {
+            BlockShape::I | BlockShape::J => 1,
+            BlockShape::L => 3,
+            _ => 0,
+        }
+    }
+
+    pub fn srs_state(&self) -> u8 {
+        match self.block_shape {
+            BlockShape::I | BlockShape::J => (self.rotation + 3) % 4,
+            BlockShape::L => (self.rotation + 1) % 4,
+            _ => self.rotation,
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn render(&self, game: &Game) {
-        let shape = self.get_shape();
-        for y in 0..shape.len() {
-            for x in 0..shape[y].len() {
-                if shape[y][x] != 0 {
-                    draw_texture(
-                        game.block_texture.unwrap(),
-                        self.render_position.x + x as f32 * 16.0,
-                        self.render_position.y + y as f32 * 16.0,
-                        COLORS[shape[y][x] as usize],
-                    );
+    /// The filled cells of `shape` in `state`, as (x, y) in its 4x4 box.
+    fn cells(shape: BlockShape, state: u8) -> Vec<(usize, usize)> {
+        let block = (0..4)
+            .map(|rotation| Block { rotation, ..Block::new(shape, Vec2::ZERO) })
+            .find(|block| block.srs_state() == state)
+            .unwrap();
+        let mut cells = Vec::new();
+        for (y, row) in block.get_shape().iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell != 0 {
+                    cells.push((x, y));
                 }
             }
         }
+        cells
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn srs_spawn_states_lie_flat() {
+        assert_eq!(cells(BlockShape::I, 0), [(0, 1), (1, 1), (2, 1), (3, 1)]);
+        assert_eq!(cells(BlockShape::J, 0), [(0, 0), (0, 1), (1, 1), (2, 1)]);
+        assert_eq!(cells(BlockShape::L, 0), [(2, 0), (0, 1), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn new_blocks_spawn_in_state_0() {
+        for shape in BlockShape::ALL {
+            assert_eq!(Block::new(shape, Vec2::ZERO).srs_state(), 0, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn srs_right_states_stand_up() {
+        assert_eq!(cells(BlockShape::I, 1), [(2, 0), (2, 1), (2, 2), (2, 3)]);
+        assert_eq!(cells(BlockShape::J, 1), [(1, 0), (2, 0), (1, 1), (1, 2)]);
+        assert_eq!(cells(BlockShape::L, 1), [(1, 0), (1, 1), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn turning_clockwise_steps_through_the_srs_states() {
+        for shape in BlockShape::ALL {
+            for rotation in 0..4 {
+                let block = Block { rotation, ..Block::new(shape, Vec2::ZERO) };
+                let turned = Block { rotation: (rotation + 1) % 4, ..block };
+                assert_eq!(turned.srs_state(), (block.srs_state() + 1) % 4, "{:?}", shape);
+            }
+        }
+    }
+}
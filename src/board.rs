@@ -0,0 +1,206 @@
+use macroquad::prelude::*;
+use macroquad::rand::RandGenerator;
+use crate::util::seeded_rng;
+
+/// The guideline's well: 10 columns by 20 rows.
+pub const STANDARD_WIDTH: usize = 10;
+pub const STANDARD_HEIGHT: usize = 20;
+/// Rows above the visible ones, where blocks spawn.
+pub const HIDDEN_ROWS: usize = 4;
+/// The cell value garbage rows are filled with, which is also its color.
+pub const GARBAGE: u8 = 10;
+
+/// The cells the blocks stack up in, 0 for empty or a color index. Row 0 is
+/// the top, and the first `hidden` rows are above the visible board.
+/// Everything outside the board counts as a wall.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Board {
+    width: usize,
+    hidden: usize,
+    rows: Vec<Vec<u8>>,
+}
+
+impl Board {
+    /// A board `height` rows tall, with `hidden` more on top.
+    pub fn new(width: usize, height: usize, hidden: usize) -> Board {
+        Board {
+            width,
+            hidden,
+            rows: vec![vec![0; width]; height + hidden],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// All the rows, hidden ones included.
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn hidden_rows(&self) -> usize {
+        self.hidden
+    }
+
+    pub fn visible_height(&self) -> usize {
+        self.rows.len() - self.hidden
+    }
+
+    /// Whether row `y` is above the visible board.
+    pub fn is_hidden(&self, y: i32) -> bool {
+        y < self.hidden as i32
+    }
+
+    /// The rows from the top down, hidden ones included.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.rows.iter().map(|row| row.as_slice())
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.rows[y]
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0
+        && y >= 0
+        && (x as usize) < self.width
+        && (y as usize) < self.rows.len()
+    }
+
+    /// Whether a block can't go in this cell, because something is there or
+    /// it's off the board.
+    pub fn is_blocked(&self, x: i32, y: i32) -> bool {
+        !self.contains(x, y) || self.rows[y as usize][x as usize] != 0
+    }
+
+    /// Fills in a cell. Cells off the board are ignored.
+    pub fn set(&mut self, x: i32, y: i32, cell: u8) {
+        if self.contains(x, y) {
+            self.rows[y as usize][x as usize] = cell;
+        }
+    }
+
+    pub fn is_row_full(&self, y: usize) -> bool {
+        self.rows[y].iter().all(|&cell| cell != 0)
+    }
+
+    /// Takes out a row, dropping everything above it down by one.
+    pub fn remove_row(&mut self, y: usize) {
+        self.rows.remove(y);
+        self.rows.insert(0, vec![0; self.width]);
+    }
+
+    /// Adds a row at the bottom, pushing everything up by one. Returns
+    /// whether the top row had anything in it, which is lost.
+    pub fn push_row(&mut self, row: Vec<u8>) -> bool {
+        let top = self.rows.remove(0);
+        self.rows.push(row);
+        top.iter().any(|&cell| cell != 0)
+    }
+
+    /// Pushes the board up by `rows` rows from `garbage`. Returns whether
+    /// anything was pushed off the top, which should top the player out.
+    pub fn add_garbage(&mut self, garbage: &mut GarbageGenerator, rows: usize) -> bool {
+        let mut overflowed = false;
+        for _ in 0..rows {
+            let row = garbage.next_row(self.width);
+            overflowed |= self.push_row(row);
+        }
+        overflowed
+    }
+
+    /// Throws away the bottom `rows` rows, dropping everything above them
+    /// down.
+    pub fn remove_bottom_rows(&mut self, rows: usize) {
+        let rows = rows.min(self.rows.len());
+        self.rows.truncate(self.rows.len() - rows);
+        for _ in 0..rows {
+            self.rows.insert(0, vec![0; self.width]);
+        }
+    }
+
+    /// Where a block's 4x4 shape starts to appear in the middle (rounding to
+    /// the left), in the two hidden rows just above the visible board.
+    pub fn spawn_position(&self) -> Vec2 {
+        vec2((self.width as f32 / 2.0 - 2.0).floor(), self.hidden as f32 - 2.0)
+    }
+}
+
+/// Makes rows of garbage, each full apart from a single hole.
+pub struct GarbageGenerator {
+    rng: RandGenerator,
+    /// The chance of each hole moving away from the one below it, so 0 makes
+    /// one straight well and 1 a new hole every row.
+    messiness: f32,
+    /// The column of the hole in the last row made.
+    hole: Option<usize>,
+}
+
+impl GarbageGenerator {
+    pub fn new(seed: u64, messiness: f32) -> GarbageGenerator {
+        GarbageGenerator {
+            rng: seeded_rng(seed),
+            messiness,
+            hole: None,
+        }
+    }
+
+    /// The next row for a board `width` cells wide.
+    pub fn next_row(&mut self, width: usize) -> Vec<u8> {
+        let hole = match self.hole {
+            Some(hole) if self.rng.gen_range(0.0, 1.0) >= self.messiness => hole,
+            Some(hole) => {
+                // Somewhere else in the well, not the same column again.
+                let other = self.rng.gen_range(0, width - 1);
+                if other >= hole { other + 1 } else { other }
+            },
+            None => self.rng.gen_range(0, width),
+        };
+        self.hole = Some(hole);
+        (0..width).map(|x| if x == hole { 0 } else { GARBAGE }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holes(row: &[u8]) -> Vec<usize> {
+        (0..row.len()).filter(|&x| row[x] == 0).collect()
+    }
+
+    #[test]
+    fn garbage_rows_have_one_hole() {
+        let mut garbage = GarbageGenerator::new(7, 1.0);
+        let mut last = None;
+        for _ in 0..50 {
+            let row = garbage.next_row(10);
+            let holes = holes(&row);
+            assert_eq!(holes.len(), 1);
+            assert!(row.iter().all(|&cell| cell == 0 || cell == GARBAGE));
+            // Fully messy garbage never lines two holes up.
+            assert_ne!(Some(holes[0]), last);
+            last = Some(holes[0]);
+        }
+    }
+
+    #[test]
+    fn clean_garbage_keeps_one_well() {
+        let mut garbage = GarbageGenerator::new(7, 0.0);
+        let well = holes(&garbage.next_row(10));
+        for _ in 0..50 {
+            assert_eq!(holes(&garbage.next_row(10)), well);
+        }
+    }
+
+    #[test]
+    fn pushing_the_stack_through_the_top_overflows() {
+        let mut board = Board::new(10, 20, HIDDEN_ROWS);
+        let mut garbage = GarbageGenerator::new(7, 0.5);
+        assert!(!board.add_garbage(&mut garbage, board.height() - 1));
+        assert!(!board.add_garbage(&mut garbage, 1));
+        assert!(board.add_garbage(&mut garbage, 1));
+    }
+}
+
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    Hold,
+    Restart,
+    Pause,
+    MenuUp,
+    MenuDown,
+    MenuLeft,
+    MenuRight,
+    Confirm,
+    Back,
+}
+
+impl Action {
+    pub const ALL: [Action; 16] = [
+        Action::Left,
+        Action::Right,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::RotateCw,
+        Action::RotateCcw,
+        Action::Rotate180,
+        Action::Hold,
+        Action::Restart,
+        Action::Pause,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::MenuLeft,
+        Action::MenuRight,
+        Action::Confirm,
+        Action::Back,
+    ];
+
+    /// The name used in the controls file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::SoftDrop => "soft_drop",
+            Action::HardDrop => "hard_drop",
+            Action::RotateCw => "rotate_cw",
+            Action::RotateCcw => "rotate_ccw",
+            Action::Rotate180 => "rotate_180",
+            Action::Hold => "hold",
+            Action::Restart => "restart",
+            Action::Pause => "pause",
+            Action::MenuUp => "menu_up",
+            Action::MenuDown => "menu_down",
+            Action::MenuLeft => "menu_left",
+            Action::MenuRight => "menu_right",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+        }
+    }
+
+    /// The name shown on the controls screen.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::SoftDrop => "Soft drop",
+            Action::HardDrop => "Hard drop",
+            Action::RotateCw => "Rotate CW",
+            Action::RotateCcw => "Rotate CCW",
+            Action::Rotate180 => "Rotate 180",
+            Action::Hold => "Hold",
+            Action::Restart => "Play again",
+            Action::Pause => "Pause",
+            Action::MenuUp => "Menu up",
+            Action::MenuDown => "Menu down",
+            Action::MenuLeft => "Menu left",
+            Action::MenuRight => "Menu right",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+        }
+    }
+
+    pub fn default_keys(&self) -> Vec<KeyCode> {
+        match self {
+            Action::Left => vec![KeyCode::Left],
+            Action::Right => vec![KeyCode::Right],
+            Action::SoftDrop => vec![KeyCode::Down],
+            Action::HardDrop => vec![KeyCode::X, KeyCode::Space],
+            Action::RotateCw => vec![KeyCode::Z, KeyCode::Up],
+            Action::RotateCcw => vec![KeyCode::A],
+            Action::Rotate180 => vec![KeyCode::S],
+            Action::Hold => vec![KeyCode::C, KeyCode::LeftShift],
+            Action::Restart => vec![KeyCode::R, KeyCode::Enter],
+            Action::Pause => vec![KeyCode::Escape, KeyCode::P],
+            Action::MenuUp => vec![KeyCode::Up],
+            Action::MenuDown => vec![KeyCode::Down],
+            Action::MenuLeft => vec![KeyCode::Left],
+            Action::MenuRight => vec![KeyCode::Right],
+            Action::Confirm => vec![KeyCode::Enter, KeyCode::X],
+            Action::Back => vec![KeyCode::Escape],
+        }
+    }
+}
+
+/// Which keys trigger each action. An action can have any number of keys.
+/// Saved as a table of action names to lists of key names.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, Vec<String>>", into = "BTreeMap<String, Vec<String>>")]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            keys: Action::ALL.iter()
+                .map(|&action| (action, action.default_keys()))
+                .collect(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.keys.get(&action).map(|keys| keys.as_slice()).unwrap_or(&[])
+    }
+
+    /// Whether `check` is true for any key bound to `action`, so this works
+    /// with `is_key_down`, `is_key_pressed` and friends.
+    pub fn any(&self, action: Action, check: fn(KeyCode) -> bool) -> bool {
+        self.keys(action).iter().any(|&key| check(key))
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.any(action, is_key_down)
+    }
+
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.any(action, is_key_pressed)
+    }
+
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        let keys = self.keys.entry(action).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.keys.insert(action, Vec::new());
+    }
+
+    /// Gives any action with no keys its defaults back, so nothing (least of
+    /// all the menus) can become unreachable.
+    pub fn fill_unbound(&mut self) {
+        for action in Action::ALL {
+            if self.keys(action).is_empty() {
+                self.keys.insert(action, action.default_keys());
+            }
+        }
+    }
+
+    /// Short description of the keys for `action`, like "X/Space".
+    pub fn describe(&self, action: Action) -> String {
+        self.keys(action)
+            .iter()
+            .map(|&key| key_name(key))
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+impl From<Bindings> for BTreeMap<String, Vec<String>> {
+    fn from(bindings: Bindings) -> BTreeMap<String, Vec<String>> {
+        Action::ALL.iter()
+            .map(|&action| (
+                action.name().to_string(),
+                bindings.keys(action).iter().map(|&key| key_name(key)).collect(),
+            ))
+            .collect()
+    }
+}
+
+/// Keeps the defaults for any action missing from `names`, and skips key
+/// names that aren't recognized.
+impl From<BTreeMap<String, Vec<String>>> for Bindings {
+    fn from(names: BTreeMap<String, Vec<String>>) -> Bindings {
+        let mut bindings = Bindings::default();
+        for action in Action::ALL {
+            if let Some(keys) = names.get(action.name()) {
+                bindings.keys.insert(action, keys.iter().filter_map(|name| key_from_name(name)).collect());
+            }
+        }
+        bindings.fill_unbound();
+        bindings
+    }
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().copied().find(|&key| key_name(key) == name)
+}
+
+/// Every key that can be bound.
+const KEYS: [KeyCode; 120] = [
+    KeyCode::Space, KeyCode::Apostrophe, KeyCode::Comma, KeyCode::Minus, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::Semicolon, KeyCode::Equal,
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::LeftBracket, KeyCode::Backslash, KeyCode::RightBracket, KeyCode::GraveAccent,
+    KeyCode::World1, KeyCode::World2,
+    KeyCode::Escape, KeyCode::Enter, KeyCode::Tab, KeyCode::Backspace, KeyCode::Insert, KeyCode::Delete,
+    KeyCode::Right, KeyCode::Left, KeyCode::Down, KeyCode::Up,
+    KeyCode::PageUp, KeyCode::PageDown, KeyCode::Home, KeyCode::End,
+    KeyCode::CapsLock, KeyCode::ScrollLock, KeyCode::NumLock, KeyCode::PrintScreen, KeyCode::Pause,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6, KeyCode::F7,
+    KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12, KeyCode::F13,
+    KeyCode::F14, KeyCode::F15, KeyCode::F16, KeyCode::F17, KeyCode::F18, KeyCode::F19,
+    KeyCode::F20, KeyCode::F21, KeyCode::F22, KeyCode::F23, KeyCode::F24, KeyCode::F25,
+    KeyCode::Kp0, KeyCode::Kp1, KeyCode::Kp2, KeyCode::Kp3, KeyCode::Kp4,
+    KeyCode::Kp5, KeyCode::Kp6, KeyCode::Kp7, KeyCode::Kp8, KeyCode::Kp9,
+    KeyCode::KpDecimal, KeyCode::KpDivide, KeyCode::KpMultiply, KeyCode::KpSubtract,
+    KeyCode::KpAdd, KeyCode::KpEnter, KeyCode::KpEqual,
+    KeyCode::LeftShift, KeyCode::LeftControl, KeyCode::LeftAlt, KeyCode::LeftSuper,
+    KeyCode::RightShift, KeyCode::RightControl, KeyCode::RightAlt, KeyCode::RightSuper,
+    KeyCode::Menu,
+];
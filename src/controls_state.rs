@@ -0,0 +1,63 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::menu::update_menu_widget;
+use crate::menu::MenuEntry;
+use crate::menu::MenuEvent;
+use crate::Game;
+
+fn controls_entries(game: &Game) -> Vec<MenuEntry> {
+    Action::ALL.iter()
+        .enumerate()
+        .map(|(i, &action)| {
+            let keys = if i == game.controls_menu.selection && game.controls_listening {
+                "press a key...".to_string()
+            } else {
+                game.settings.bindings.describe(action)
+            };
+            MenuEntry::with_value(action.label(), keys)
+        })
+        .collect()
+}
+
+pub fn update_controls(game: &mut Game) {
+    let action = Action::ALL[game.controls_menu.selection];
+    if game.controls_listening {
+        if let Some(key) = get_last_key_pressed() {
+            if key != KeyCode::Escape {
+                game.settings.bindings.bind(action, key);
+            }
+            game.controls_listening = false;
+        }
+        return;
+    }
+
+    if is_key_pressed(KeyCode::Delete) {
+        game.settings.bindings.clear(action);
+    } else if is_key_pressed(KeyCode::Backspace) {
+        game.settings.bindings.clear(action);
+        game.settings.bindings.fill_unbound();
+    }
+    let entries = controls_entries(game);
+    match update_menu_widget(game, |game| &mut game.controls_menu, &entries) {
+        Some(MenuEvent::Select(_)) => game.controls_listening = true,
+        Some(MenuEvent::Back) => {
+            game.settings.bindings.fill_unbound();
+            game.save_settings();
+            game.state = game.screen_return;
+        },
+        _ => {},
+    }
+}
+
+pub fn render_controls(game: &Game) {
+    let bindings = &game.settings.bindings;
+    draw_text("Controls", 110.0, 16.0, 32.0, WHITE);
+    game.controls_menu.render(&controls_entries(game), 0.0, 34.0, 13.0, 100.0);
+    draw_text(
+        &format!("{}: add key  Del: clear  Backspace: reset  {}: back", bindings.describe(Action::Confirm), bindings.describe(Action::Back)),
+        -8.0,
+        248.0,
+        12.0,
+        WHITE,
+    );
+}
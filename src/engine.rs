@@ -0,0 +1,1282 @@
+use std::collections::VecDeque;
+use crate::block::Block;
+use crate::block::BlockShape;
+use crate::board::Board;
+use crate::board::GarbageGenerator;
+use crate::board::GARBAGE;
+use crate::board::HIDDEN_ROWS;
+use crate::generator::PieceGenerator;
+use crate::mode::Goal;
+use crate::mode::SpeedCurve;
+use crate::mode::TopOut;
+use crate::mode::LockReset;
+use crate::mode::MAX_PREVIEWS;
+use crate::mode::Rules;
+use crate::rotation::Rotation;
+use crate::scoring::Score;
+use crate::scoring::GRADES;
+use crate::scoring::MAX_TGM_LEVEL;
+use crate::settings::Handling;
+
+/// A set of game buttons, one flag per action.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Buttons {
+    pub left: bool,
+    pub right: bool,
+    pub soft_drop: bool,
+    pub hard_drop: bool,
+    pub rotate_cw: bool,
+    pub rotate_ccw: bool,
+    pub rotate_180: bool,
+    pub hold: bool,
+}
+
+impl Buttons {
+    /// Every button set in either.
+    pub fn union(self, other: Buttons) -> Buttons {
+        Buttons {
+            left: self.left || other.left,
+            right: self.right || other.right,
+            soft_drop: self.soft_drop || other.soft_drop,
+            hard_drop: self.hard_drop || other.hard_drop,
+            rotate_cw: self.rotate_cw || other.rotate_cw,
+            rotate_ccw: self.rotate_ccw || other.rotate_ccw,
+            rotate_180: self.rotate_180 || other.rotate_180,
+            hold: self.hold || other.hold,
+        }
+    }
+}
+
+/// Everything the engine needs to know about the player for one update.
+/// `held` is the current state of each button and `pressed` is only set on
+/// the update where the button went down.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Input {
+    pub held: Buttons,
+    pub pressed: Buttons,
+}
+
+/// Things that happened during an update, so the frontend can play sounds,
+/// shake the screen, etc. without the engine knowing about any of it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Event {
+    HardDrop,
+    Hold,
+    /// The next block came off the queue and onto the board.
+    Spawned,
+    PieceLocked,
+    LinesCleared(usize),
+    LevelUp(u32),
+    GameOver,
+    /// The mode's goal was reached, which also ends the game.
+    Finished,
+    /// The stack reached the top in a mode without game overs, so the bottom
+    /// of the board was taken away.
+    Compressed,
+}
+
+/// Running totals for the summary at the end of a session.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct Stats {
+    pub pieces: u32,
+    /// Singles, doubles, triples and tetrises.
+    pub clears: [u32; 4],
+    pub t_spins: u32,
+    pub max_combo: i32,
+    pub holds: u32,
+    pub compressions: u32,
+}
+
+/// Keeps the garbage holes apart from the piece stream for the same seed.
+const GARBAGE_STREAM: u64 = 0x5851f42d4c957f2d;
+/// Most garbage rows a dig race has on the board at once.
+const DIG_VISIBLE_ROWS: u32 = 8;
+
+/// Lines between each split time.
+pub const SPLIT_LINES: u32 = 10;
+
+/// Frames per row on each NES level, from 0 up to 28. It's one row a frame
+/// from 29 on.
+const NES_GRAVITY: [f32; 29] = [
+    48.0, 43.0, 38.0, 33.0, 28.0, 23.0, 18.0, 13.0, 8.0, 6.0,
+    5.0, 5.0, 5.0, 4.0, 4.0, 4.0, 3.0, 3.0, 3.0, 2.0,
+    2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0,
+];
+
+/// The guideline curve is 20G by this level, and stops making sense after it.
+const GUIDELINE_TOP_LEVEL: u32 = 20;
+
+/// Master's gravity in 256ths of a row per frame, from each level on.
+const MASTER_GRAVITY: [(u32, f32); 30] = [
+    (0, 4.0), (30, 6.0), (35, 8.0), (40, 10.0), (50, 12.0), (60, 16.0), (70, 32.0),
+    (80, 48.0), (90, 64.0), (100, 80.0), (120, 96.0), (140, 112.0), (160, 128.0),
+    (170, 144.0), (200, 4.0), (220, 32.0), (230, 64.0), (233, 96.0), (236, 128.0),
+    (239, 160.0), (243, 192.0), (247, 224.0), (251, 256.0), (300, 512.0),
+    (330, 768.0), (360, 1024.0), (400, 1280.0), (420, 1024.0), (450, 768.0),
+    (500, 5120.0),
+];
+
+/// Master's ARE, line clear delay and lock delay, in frames, from each level on.
+const MASTER_DELAYS: [(u32, f32, f32, f32); 6] = [
+    (0, 25.0, 40.0, 30.0),
+    (500, 25.0, 25.0, 30.0),
+    (600, 25.0, 16.0, 30.0),
+    (700, 16.0, 12.0, 30.0),
+    (800, 12.0, 6.0, 30.0),
+    (900, 12.0, 6.0, 17.0),
+];
+
+/// What it takes to be a grand master on top of reaching the last level.
+const GM_SCORE: u32 = 126000;
+const GM_FRAMES: f64 = 13.5 * 60.0 * 60.0;
+
+/// Moves and rotations that can push back the lock timer on one piece, like
+/// the guideline's move reset.
+const MAX_LOCK_RESETS: u32 = 15;
+
+/// The rules of the game, with no rendering, audio or window access.
+pub struct Engine {
+    pub rules: Rules,
+    pub seed: u64,
+    pub board: Board,
+    pub block: Block,
+    /// The upcoming shapes, next first.
+    pub queue: VecDeque<BlockShape>,
+    pub held: Option<BlockShape>,
+    pub has_switched: bool,
+    pub game_over: bool,
+    /// Whether the game ended by reaching its goal rather than topping out.
+    pub finished: bool,
+    pub score: Score,
+    /// Frames played so far, kept in double precision so long games stay
+    /// accurate to the millisecond.
+    pub frames: f64,
+    /// The frame count every `SPLIT_LINES` lines (or every section of 100
+    /// levels in Master) was reached at.
+    pub splits: Vec<f64>,
+    /// The grade held at the end of each section, for Master.
+    pub section_grades: Vec<usize>,
+    /// Frames left before the next block appears, after one locks.
+    pub spawn_delay: f32,
+    /// The rows the last line clear took out, top down, with what was in
+    /// them. They stay on the board until the line clear delay is over.
+    pub cleared_rows: Vec<(usize, Vec<u8>)>,
+    /// Garbage rows still to come up, in a dig race.
+    pub garbage_left: u32,
+    pub garbage_cleared: u32,
+    pub stats: Stats,
+    pub handling: Handling,
+
+    generator: Box<dyn PieceGenerator>,
+    /// Direction being auto shifted, -1 for left, 1 for right, 0 for neither.
+    shift_direction: i32,
+    /// The direction pressed most recently, which wins if both are held.
+    last_direction: i32,
+    das_timer: f32,
+    arr_timer: f32,
+    das_cut_timer: f32,
+    /// Frames left before `cleared_rows` are taken off the board.
+    line_clear_timer: f32,
+    /// The whole line clear delay the current clear started with.
+    line_clear_length: f32,
+    /// Whether the last thing that moved the block was a rotation, for T-spins.
+    rotated_last: bool,
+    garbage: GarbageGenerator,
+}
+
+impl Engine {
+    pub fn new(rules: Rules, handling: Handling, seed: u64) -> Engine {
+        let board = Board::new(rules.board_width, rules.board_height, HIDDEN_ROWS);
+        let mut generator = rules.generator.create(seed);
+        let first_shape = generator.next_shape();
+        let queue = (0..rules.previews.clamp(1, MAX_PREVIEWS))
+            .map(|_| generator.next_shape())
+            .collect();
+        let mut engine = Engine {
+            rules,
+            seed,
+            block: Block::new(first_shape, board.spawn_position()),
+            board,
+            queue,
+            held: None,
+            has_switched: false,
+            game_over: false,
+            finished: false,
+            score: Score::new(rules.start_level, rules.lines_per_level, rules.scoring),
+            frames: 0.0,
+            splits: Vec::new(),
+            section_grades: Vec::new(),
+            spawn_delay: 0.0,
+            cleared_rows: Vec::new(),
+            garbage_left: 0,
+            garbage_cleared: 0,
+            stats: Stats::default(),
+            handling,
+
+            generator,
+            shift_direction: 0,
+            last_direction: 0,
+            das_timer: 0.0,
+            arr_timer: 0.0,
+            das_cut_timer: 0.0,
+            line_clear_timer: 0.0,
+            line_clear_length: 0.0,
+            rotated_last: false,
+            garbage: GarbageGenerator::new(seed.wrapping_add(GARBAGE_STREAM), rules.garbage_messiness),
+        };
+        engine.block = engine.spawn_block(first_shape);
+        if let Goal::Garbage(rows) = rules.goal {
+            engine.garbage_left = rows;
+            // An empty board has room for it all.
+            engine.refill_garbage(&mut Vec::new());
+        }
+        engine
+    }
+
+    /// Frames between each row the block falls on its own, from the mode's
+    /// speed curve.
+    pub fn gravity_delay(&self) -> f32 {
+        match self.rules.speed {
+            SpeedCurve::Guideline => {
+                let level = self.score.level.min(GUIDELINE_TOP_LEVEL) as f32 - 1.0;
+                (0.8 - level * 0.007).powf(level) * 60.0
+            },
+            SpeedCurve::Nes => NES_GRAVITY.get(self.score.level as usize).copied().unwrap_or(1.0),
+            SpeedCurve::Master => {
+                let (_, gravity) = MASTER_GRAVITY.iter().rev().find(|&&(level, _)| self.score.level >= level).unwrap();
+                256.0 / gravity
+            },
+        }
+    }
+
+    /// Master's delays for the current section as (ARE, line clear, lock), or
+    /// the mode's fixed delays for everything else.
+    fn delays(&self) -> (f32, f32, f32) {
+        if self.rules.speed == SpeedCurve::Master {
+            let &(_, are, line_clear, lock) = MASTER_DELAYS.iter().rev().find(|&&(level, ..)| self.score.level >= level).unwrap();
+            (are, line_clear, lock)
+        } else {
+            let lock_delay = if self.rules.player_lock { self.handling.lock_delay } else { self.rules.lock_delay };
+            let line_clear_delay = match self.handling.line_clear_delay {
+                Some(delay) if self.rules.player_line_clear => delay,
+                _ => self.rules.line_clear_delay,
+            };
+            (self.rules.are, line_clear_delay, lock_delay)
+        }
+    }
+
+    /// What gives a block on the ground more time: the mode's rule, or the
+    /// player's if the mode leaves it to them.
+    fn lock_reset(&self) -> LockReset {
+        if self.rules.player_lock { self.handling.lock_reset } else { self.rules.lock_reset }
+    }
+
+    pub fn lock_delay(&self) -> f32 {
+        self.delays().2
+    }
+
+    /// Whether cleared rows are still on the board, waiting for the line
+    /// clear delay.
+    pub fn is_clearing(&self) -> bool {
+        self.line_clear_timer > 0.0
+    }
+
+    /// How far through the line clear delay the game is, from 0 as the rows
+    /// fill to 1 once they're gone.
+    pub fn line_clear_progress(&self) -> f32 {
+        if self.is_clearing() {
+            1.0 - self.line_clear_timer / self.line_clear_length
+        } else {
+            1.0
+        }
+    }
+
+    /// The grade earned so far, as an index into `GRADES`.
+    pub fn grade(&self) -> usize {
+        if self.finished
+        && self.score.score >= GM_SCORE
+        && self.frames <= GM_FRAMES {
+            return GRADES.len() - 1;
+        }
+        self.score.grade()
+    }
+
+    fn spawn_block(&self, block_shape: BlockShape) -> Block {
+        Block {
+            gravity_timer: self.gravity_delay(),
+            lock_timer: self.lock_delay(),
+            ..Block::new(block_shape, self.board.spawn_position())
+        }
+    }
+
+    /// Takes the next block off the queue, topping it back up from the
+    /// generator.
+    fn take_next(&mut self) -> BlockShape {
+        let next = self.queue.pop_front().unwrap();
+        self.queue.push_back(self.generator.next_shape());
+        next
+    }
+
+    /// Brings in the next block. At over 1G it falls the rows it would have
+    /// fallen in its first frame straight away, so at 20G it appears on the
+    /// ground.
+    fn spawn_next(&mut self, events: &mut Vec<Event>) {
+        self.score.piece_spawned();
+        let shape = self.take_next();
+        self.block = self.spawn_block(shape);
+        events.push(Event::Spawned);
+        self.rotated_last = false;
+        self.check_block_out(events);
+        if self.game_over {
+            return;
+        }
+        let delay = self.gravity_delay();
+        if delay < 1.0 {
+            for _ in 0..(1.0 / delay) as u32 {
+                if !self.fall(false) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Advances the game by `dt` frames (1.0 = one 60th of a second).
+    pub fn update(&mut self, input: Input, dt: f32) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.game_over {
+            return events;
+        }
+
+        self.frames += dt as f64;
+        if self.spawn_delay > 0.0 {
+            if self.is_clearing() {
+                self.line_clear_timer -= dt;
+                if !self.is_clearing() {
+                    self.remove_cleared_rows();
+                    self.refill_garbage(&mut events);
+                    if self.game_over {
+                        return events;
+                    }
+                }
+            }
+            self.spawn_delay -= dt;
+            self.charge_das(input, dt);
+            if self.spawn_delay <= 0.0 {
+                self.spawn_delay = 0.0;
+                self.spawn_next(&mut events);
+            }
+        } else {
+            self.update_block(input, dt, &mut events);
+        }
+
+        while (self.splits.len() as u32) < self.split_count() {
+            self.splits.push(self.frames);
+            if self.rules.goal == Goal::Level(MAX_TGM_LEVEL) {
+                self.section_grades.push(self.grade());
+            }
+        }
+        if self.goal_reached() {
+            if let Goal::Time(seconds) = self.rules.goal {
+                // The last update usually runs a little past the limit.
+                self.frames = seconds as f64 * 60.0;
+            }
+            self.game_over = true;
+            self.finished = true;
+            if let Goal::Level(_) = self.rules.goal {
+                // The last section ends on reaching the goal rather than
+                // crossing into the next one.
+                self.splits.push(self.frames);
+                if self.rules.goal == Goal::Level(MAX_TGM_LEVEL) {
+                    // Its grade counts the finish, for GM.
+                    self.section_grades.push(self.grade());
+                }
+            }
+            events.push(Event::Finished);
+        }
+        events
+    }
+
+    /// The stack got too high. The game ends, or in modes without game overs
+    /// the board is compressed to make room.
+    fn top_out(&mut self, events: &mut Vec<Event>) {
+        match self.rules.top_out {
+            TopOut::GameOver => self.end_game(events),
+            TopOut::Compress => {
+                self.compress();
+                events.push(Event::Compressed);
+            },
+        }
+    }
+
+    fn end_game(&mut self, events: &mut Vec<Event>) {
+        self.game_over = true;
+        events.push(Event::GameOver);
+    }
+
+    /// A block that spawns overlapping the stack is a block out. Compressing
+    /// as many times as the board has rows empties it, so a block that still
+    /// doesn't fit after that never will, and the game ends anyway.
+    fn check_block_out(&mut self, events: &mut Vec<Event>) {
+        let mut compressions_left = self.board.height();
+        while self.block_collides()
+        && !self.game_over {
+            if compressions_left == 0 {
+                self.end_game(events);
+                return;
+            }
+            compressions_left -= 1;
+            self.top_out(events);
+        }
+    }
+
+    fn goal_reached(&self) -> bool {
+        match self.rules.goal {
+            Goal::Endless => false,
+            Goal::Lines(lines) => self.score.lines >= lines,
+            Goal::Time(seconds) => self.frames >= seconds as f64 * 60.0,
+            Goal::Level(level) => self.score.level >= level,
+            Goal::Garbage(rows) => self.garbage_cleared >= rows,
+        }
+    }
+
+    /// How many splits there should be by now: one every `SPLIT_LINES` lines,
+    /// or one per section when playing to a level, as the level crosses into
+    /// the next. The stop at a section's 99 counts towards the section.
+    fn split_count(&self) -> u32 {
+        match self.rules.goal {
+            Goal::Level(_) => self.score.level / 100,
+            _ => self.score.lines / SPLIT_LINES,
+        }
+    }
+
+    fn update_block(&mut self, input: Input, dt: f32, events: &mut Vec<Event>) {
+        if input.pressed.rotate_cw {
+            self.rotate(Rotation::Clockwise);
+        }
+        if input.pressed.rotate_ccw {
+            self.rotate(Rotation::CounterClockwise);
+        }
+        if input.pressed.rotate_180 {
+            self.rotate(Rotation::Half);
+        }
+        if input.pressed.hard_drop
+        && self.rules.hard_drop {
+            events.push(Event::HardDrop);
+            let cells = self.drop_distance();
+            self.block.position.y += cells as f32;
+            self.score.hard_drop(cells);
+            if cells > 0 {
+                self.rotated_last = false;
+            }
+            self.lock_block(events);
+            return;
+        }
+        if input.pressed.hold
+        && self.rules.hold
+        && !self.has_switched {
+            self.has_switched = true;
+            events.push(Event::Hold);
+            self.stats.holds += 1;
+            let shape = match self.held.replace(self.block.block_shape) {
+                Some(shape) => shape,
+                None => {
+                    events.push(Event::Spawned);
+                    self.take_next()
+                },
+            };
+            self.block = self.spawn_block(shape);
+            self.rotated_last = false;
+            self.check_block_out(events);
+            if self.game_over {
+                return;
+            }
+        }
+        self.update_shift(input, dt);
+
+        let soft_drop = input.held.soft_drop;
+        if soft_drop
+        && self.handling.soft_drop_factor.is_infinite() {
+            let mut fell = false;
+            while self.fall(true) {
+                fell = true;
+            }
+            // Without a lock delay, pushing down on the floor locks the block,
+            // the way gravity would.
+            if !fell
+            && self.lock_reset() == LockReset::Gravity {
+                self.lock_block(events);
+                return;
+            }
+            self.block.gravity_timer = self.gravity_delay();
+        } else {
+            let delay = if soft_drop {
+                self.gravity_delay() / self.handling.soft_drop_factor
+            } else {
+                self.gravity_delay()
+            };
+            if input.pressed.soft_drop {
+                self.block.gravity_timer = 0.0;
+            }
+            self.block.gravity_timer = self.block.gravity_timer.min(delay) - dt;
+            while self.block.gravity_timer <= 0.0 {
+                self.block.gravity_timer += delay;
+                if !self.fall(soft_drop) {
+                    self.block.gravity_timer = delay;
+                    if self.lock_reset() == LockReset::Gravity {
+                        self.lock_block(events);
+                        return;
+                    }
+                    break;
+                }
+            }
+        }
+
+        if self.drop_distance() == 0
+        && self.lock_reset() != LockReset::Gravity {
+            self.block.lock_timer -= dt;
+            if self.block.lock_timer <= 0.0 {
+                self.lock_block(events);
+            }
+        }
+    }
+
+    /// Moves the block sideways for held directions: once straight away, then
+    /// repeatedly once DAS has charged.
+    fn update_shift(&mut self, input: Input, dt: f32) {
+        let direction = self.held_direction(input);
+        self.das_cut_timer -= dt;
+
+        if direction != self.shift_direction {
+            self.shift_direction = direction;
+            self.das_timer = self.handling.das;
+            self.arr_timer = 0.0;
+            if direction != 0 {
+                self.shift(direction);
+            }
+            return;
+        }
+        if direction == 0 {
+            return;
+        }
+
+        self.das_timer -= dt;
+        if self.das_timer > 0.0
+        || self.das_cut_timer > 0.0 {
+            return;
+        }
+        if self.handling.arr <= 0.0 {
+            while self.shift(direction) {}
+            return;
+        }
+        self.arr_timer -= dt;
+        while self.arr_timer <= 0.0 {
+            self.arr_timer += self.handling.arr;
+            if !self.shift(direction) {
+                break;
+            }
+        }
+    }
+
+    /// The direction being held, -1 for left, 1 for right, 0 for neither.
+    fn held_direction(&mut self, input: Input) -> i32 {
+        if input.pressed.left {
+            self.last_direction = -1;
+        }
+        if input.pressed.right {
+            self.last_direction = 1;
+        }
+        match (input.held.left, input.held.right) {
+            (true, true) => self.last_direction,
+            (true, false) => -1,
+            (false, true) => 1,
+            (false, false) => 0,
+        }
+    }
+
+    /// Lets DAS charge while waiting for the next block, so it can start
+    /// moving the moment it appears.
+    fn charge_das(&mut self, input: Input, dt: f32) {
+        let direction = self.held_direction(input);
+        if direction != self.shift_direction {
+            self.shift_direction = direction;
+            self.das_timer = self.handling.das;
+            self.arr_timer = 0.0;
+        } else {
+            self.das_timer -= dt;
+        }
+    }
+
+    fn shift(&mut self, direction: i32) -> bool {
+        self.block.position.x += direction as f32;
+        if self.block_collides() {
+            self.block.position.x -= direction as f32;
+            return false;
+        }
+        self.rotated_last = false;
+        self.reset_lock_timer();
+        true
+    }
+
+    /// Drops the block one row if it can, scoring it if it was a soft drop.
+    fn fall(&mut self, soft_drop: bool) -> bool {
+        self.block.position.y += 1.0;
+        if self.block_collides() {
+            self.block.position.y -= 1.0;
+            return false;
+        }
+        self.rotated_last = false;
+        if soft_drop {
+            self.score.soft_drop(1);
+        }
+        if self.block.position.y > self.block.lowest_y {
+            self.block.lowest_y = self.block.position.y;
+            if self.lock_reset() != LockReset::None {
+                self.block.lock_timer = self.lock_delay();
+                self.block.lock_resets = 0;
+            }
+        }
+        true
+    }
+
+    /// Moving or rotating the block gives it more time on the ground, but only
+    /// so many times before it has to lock.
+    fn reset_lock_timer(&mut self) {
+        if self.lock_reset() == LockReset::Move
+        && self.block.lock_resets < MAX_LOCK_RESETS {
+            self.block.lock_timer = self.lock_delay();
+            self.block.lock_resets += 1;
+        }
+    }
+
+    /// How close the block is to locking, from 0 (just landed or in the air)
+    /// to 1 (about to lock).
+    pub fn lock_progress(&self) -> f32 {
+        if self.drop_distance() > 0 {
+            return 0.0;
+        }
+        if self.lock_reset() == LockReset::Gravity {
+            return 1.0 - (self.block.gravity_timer / self.gravity_delay()).clamp(0.0, 1.0);
+        }
+        1.0 - (self.block.lock_timer / self.lock_delay()).clamp(0.0, 1.0)
+    }
+
+    /// Turns the active block using the mode's rotation system, trying each
+    /// kick in order. Returns false and leaves the block alone if none fit.
+    fn rotate(&mut self, rotation: Rotation) -> bool {
+        let original = self.block;
+        let kicks = self.rules.rotation_system.kicks(&self.block, rotation);
+        self.block.rotation = (self.block.rotation + rotation.turns()) % 4;
+        for kick in kicks {
+            self.block.position = original.position + kick;
+            if !self.block_collides() {
+                self.rotated_last = true;
+                self.das_cut_timer = self.handling.das_cut;
+                self.reset_lock_timer();
+                return true;
+            }
+        }
+        self.block = original;
+        false
+    }
+
+    /// How many rows the block can fall before it hits something.
+    pub fn drop_distance(&self) -> u32 {
+        let mut block = self.block;
+        let mut cells = 0;
+        loop {
+            block.position.y += 1.0;
+            if self.collides(&block) {
+                return cells;
+            }
+            cells += 1;
+        }
+    }
+
+    /// The row the block would land on if it was hard dropped, for the ghost.
+    pub fn landing_y(&self) -> f32 {
+        self.block.position.y + self.drop_distance() as f32
+    }
+
+    pub fn block_collides(&self) -> bool {
+        self.collides(&self.block)
+    }
+
+    fn collides(&self, block: &Block) -> bool {
+        let shape = block.get_shape();
+        for (y, row) in shape.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell == 0 {
+                    continue;
+                }
+                if self.board.is_blocked(block.position.x as i32 + x as i32, block.position.y as i32 + y as i32) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Three of the four corners around a T's center being filled after a
+    /// rotation makes it a T-spin. Walls and the floor count as filled.
+    fn is_t_spin(&self) -> bool {
+        if self.block.block_shape != BlockShape::T
+        || !self.rotated_last {
+            return false;
+        }
+        let corners = [(0, 0), (2, 0), (0, 2), (2, 2)];
+        corners.iter()
+            .filter(|&&(x, y)| self.board.is_blocked(self.block.position.x as i32 + x, self.block.position.y as i32 + y))
+            .count() >= 3
+    }
+
+    fn lock_block(&mut self, events: &mut Vec<Event>) {
+        let t_spin = self.is_t_spin();
+        self.has_switched = false;
+        let shape = self.block.get_shape();
+        // Locking without a single cell on the visible board is a lock out.
+        let mut lock_out = true;
+        for (y, row) in shape.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell != 0 {
+                    let board_y = self.block.position.y as i32 + y as i32;
+                    self.board.set(self.block.position.x as i32 + x as i32, board_y, cell);
+                    lock_out &= self.board.is_hidden(board_y);
+                }
+            }
+        }
+        events.push(Event::PieceLocked);
+        self.stats.pieces += 1;
+        if lock_out {
+            self.top_out(events);
+            if self.game_over {
+                return;
+            }
+        }
+
+        let lines = self.clear_lines();
+        if lines > 0 {
+            events.push(Event::LinesCleared(lines));
+            self.stats.clears[lines.min(4) - 1] += 1;
+        }
+        if t_spin {
+            self.stats.t_spins += 1;
+        }
+        if self.score.lock(lines as u32, t_spin) {
+            events.push(Event::LevelUp(self.score.level));
+        }
+        self.stats.max_combo = self.stats.max_combo.max(self.score.combo);
+
+        let (are, line_clear_delay, _) = self.delays();
+        if lines > 0 && line_clear_delay > 0.0 {
+            // The rows come out, and garbage comes up, once the delay is over.
+            self.line_clear_timer = line_clear_delay;
+            self.line_clear_length = line_clear_delay;
+        } else {
+            self.remove_cleared_rows();
+            self.refill_garbage(events);
+            if self.game_over {
+                return;
+            }
+        }
+        self.spawn_delay = if lines > 0 { are + line_clear_delay } else { are };
+        if self.spawn_delay <= 0.0 {
+            self.spawn_next(events);
+        }
+    }
+
+    /// Finds the full rows and counts them as cleared, leaving them on the
+    /// board in `cleared_rows` for now.
+    fn clear_lines(&mut self) -> usize {
+        self.cleared_rows.clear();
+        for y in 0..self.board.height() {
+            if self.board.is_row_full(y) {
+                if self.board.row(y).contains(&GARBAGE) {
+                    self.garbage_cleared += 1;
+                }
+                self.cleared_rows.push((y, self.board.row(y).to_vec()));
+            }
+        }
+        self.cleared_rows.len()
+    }
+
+    /// Takes the cleared rows off the board, dropping everything above them.
+    /// Going top down, each row taken out only moves the ones above it.
+    fn remove_cleared_rows(&mut self) {
+        self.line_clear_timer = 0.0;
+        for &(y, _) in self.cleared_rows.iter() {
+            self.board.remove_row(y);
+        }
+    }
+
+    /// Pushes the board up by `rows` rows of garbage. Pushing the stack
+    /// through the top tops out, and otherwise the active block is moved up
+    /// out of the way.
+    fn add_garbage(&mut self, rows: usize, events: &mut Vec<Event>) {
+        if self.board.add_garbage(&mut self.garbage, rows) {
+            self.top_out(events);
+        }
+        while self.block_collides()
+        && self.block.position.y > 0.0 {
+            self.block.position.y -= 1.0;
+        }
+    }
+
+    /// Keeps a dig race's garbage topped up to `DIG_VISIBLE_ROWS` rows.
+    fn refill_garbage(&mut self, events: &mut Vec<Event>) {
+        let on_board = self.board.rows().filter(|row| row.contains(&GARBAGE)).count() as u32;
+        let rows = DIG_VISIBLE_ROWS.saturating_sub(on_board).min(self.garbage_left);
+        self.garbage_left -= rows;
+        self.add_garbage(rows as usize, events);
+    }
+
+    /// Throws away the bottom half of the board (at least a row) and drops
+    /// everything above it down, making room without ending the game.
+    fn compress(&mut self) {
+        self.board.remove_bottom_rows((self.board.visible_height() / 2).max(1));
+        self.stats.compressions += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::Vec2;
+    use crate::mode::GameMode;
+    use crate::mode::Messiness;
+    use crate::util::seeded_rng;
+
+    const SEED: u64 = 7;
+
+    fn new_engine(mode: GameMode) -> Engine {
+        Engine::new(mode.rules(), Handling::default(), SEED)
+    }
+
+    fn press(buttons: Buttons) -> Input {
+        Input {
+            held: buttons,
+            pressed: buttons,
+        }
+    }
+
+    fn hard_drop() -> Input {
+        press(Buttons { hard_drop: true, ..Default::default() })
+    }
+
+    fn filled_cells(engine: &Engine) -> usize {
+        engine.board.rows().flatten().filter(|&&cell| cell != 0).count()
+    }
+
+    /// Fills `rows` from the bottom up, leaving the right column open so none
+    /// of them are full.
+    fn fill_rows(engine: &mut Engine, rows: usize) {
+        let height = engine.board.height();
+        for y in height - rows..height {
+            for x in 0..engine.board.width() - 1 {
+                engine.board.set(x as i32, y as i32, GARBAGE);
+            }
+        }
+    }
+
+    #[test]
+    fn hard_drop_locks_the_block_on_the_floor() {
+        let mut engine = new_engine(GameMode::Marathon);
+        let next = engine.queue[0];
+        let events = engine.update(hard_drop(), 1.0);
+        assert!(events.contains(&Event::PieceLocked));
+        assert!(events.contains(&Event::Spawned));
+        assert_eq!(filled_cells(&engine), 4);
+        assert_eq!(engine.stats.pieces, 1);
+        assert_eq!(engine.block.block_shape, next);
+        // Whatever the shape, something landed on the bottom row.
+        assert!(engine.board.row(engine.board.height() - 1).iter().any(|&cell| cell != 0));
+    }
+
+    #[test]
+    fn lock_delay_runs_out_on_the_ground() {
+        let mut engine = new_engine(GameMode::Marathon);
+        engine.block.position.y = engine.landing_y();
+        let lock_delay = engine.lock_delay() as usize;
+        for _ in 0..lock_delay - 1 {
+            assert!(!engine.update(Input::default(), 1.0).contains(&Event::PieceLocked));
+        }
+        assert!(engine.update(Input::default(), 1.0).contains(&Event::PieceLocked));
+    }
+
+    #[test]
+    fn full_rows_are_cleared() {
+        // Drop the first block on an empty board to see where it lands, then
+        // fill in the rest of the bottom row around it.
+        let mut probe = new_engine(GameMode::Marathon);
+        probe.update(hard_drop(), 1.0);
+        let bottom = probe.board.height() - 1;
+        let landed = probe.board.row(bottom).to_vec();
+
+        let mut engine = new_engine(GameMode::Marathon);
+        for (x, &cell) in landed.iter().enumerate() {
+            if cell == 0 {
+                engine.board.set(x as i32, bottom as i32, GARBAGE);
+            }
+        }
+        let events = engine.update(hard_drop(), 1.0);
+        assert!(events.contains(&Event::LinesCleared(1)));
+        assert_eq!(engine.score.lines, 1);
+        assert_eq!(engine.stats.clears, [1, 0, 0, 0]);
+        assert!(!engine.board.row(bottom).iter().all(|&cell| cell != 0));
+        assert_eq!(engine.cleared_rows.len(), 1);
+        assert_eq!(engine.cleared_rows[0].0, bottom);
+    }
+
+    #[test]
+    fn i_blocks_spawn_lying_flat() {
+        let engine = new_engine(GameMode::Marathon);
+        let block = engine.spawn_block(BlockShape::I);
+        let rows = block.get_shape().iter().filter(|row| row.iter().any(|&cell| cell != 0)).count();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn hold_swaps_once_per_block() {
+        let mut engine = new_engine(GameMode::Marathon);
+        let first = engine.block.block_shape;
+        let next = engine.queue[0];
+        let hold = press(Buttons { hold: true, ..Default::default() });
+
+        let events = engine.update(hold, 1.0);
+        assert!(events.contains(&Event::Hold));
+        assert_eq!(engine.held, Some(first));
+        assert_eq!(engine.block.block_shape, next);
+
+        // Not again until the block locks.
+        assert!(!engine.update(hold, 1.0).contains(&Event::Hold));
+        assert_eq!(engine.block.block_shape, next);
+
+        engine.update(hard_drop(), 1.0);
+        engine.update(hold, 1.0);
+        assert_eq!(engine.block.block_shape, first);
+        assert_eq!(engine.stats.holds, 2);
+    }
+
+    /// Ticks until a block on the floor locks while being moved back and
+    /// forth every tick.
+    fn ticks_to_lock_while_moving(handling: Handling) -> usize {
+        let mut engine = Engine::new(GameMode::Marathon.rules(), handling, SEED);
+        engine.block.position.y = engine.landing_y();
+        for tick in 1..1000 {
+            let buttons = if tick % 2 == 0 {
+                Buttons { left: true, ..Default::default() }
+            } else {
+                Buttons { right: true, ..Default::default() }
+            };
+            if engine.update(press(buttons), 1.0).contains(&Event::PieceLocked) {
+                return tick;
+            }
+        }
+        panic!("never locked");
+    }
+
+    #[test]
+    fn lock_reset_is_the_players_choice() {
+        let handling = Handling { lock_delay: 20.0, ..Handling::default() };
+        let with_reset = ticks_to_lock_while_moving(Handling { lock_reset: LockReset::Move, ..handling });
+        let without_reset = ticks_to_lock_while_moving(Handling { lock_reset: LockReset::None, ..handling });
+        assert_eq!(without_reset, 20);
+        // Every move resets the timer, up to the cap.
+        assert!(with_reset > MAX_LOCK_RESETS as usize);
+    }
+
+    #[test]
+    fn line_clear_delay_is_the_players_where_the_mode_allows() {
+        let handling = Handling { line_clear_delay: Some(15.0), ..Handling::default() };
+        let marathon = Engine::new(GameMode::Marathon.rules(), handling, SEED);
+        assert_eq!(marathon.delays().1, 15.0);
+        let sprint = Engine::new(GameMode::Sprint(40).rules(), handling, SEED);
+        assert_eq!(sprint.delays().1, 0.0);
+        // Without a choice, Zen keeps its own.
+        assert_eq!(new_engine(GameMode::Zen).delays().1, 20.0);
+    }
+
+    #[test]
+    fn modes_with_their_own_lock_ignore_the_players() {
+        let handling = Handling { lock_delay: 100.0, lock_reset: LockReset::None, ..Handling::default() };
+        let engine = Engine::new(GameMode::Master.rules(), handling, SEED);
+        assert_eq!(engine.lock_delay(), 30.0);
+        assert_eq!(engine.lock_reset(), LockReset::Step);
+    }
+
+    #[test]
+    fn instant_soft_drop_locks_without_a_lock_delay() {
+        let handling = Handling { soft_drop_factor: f32::INFINITY, ..Handling::default() };
+        let mut engine = Engine::new(GameMode::Classic(0).rules(), handling, SEED);
+        let down = Buttons { soft_drop: true, ..Default::default() };
+        let input = Input { held: down, pressed: Buttons::default() };
+        assert!(!engine.update(press(down), 1.0).contains(&Event::PieceLocked));
+        assert!(engine.update(input, 1.0).contains(&Event::PieceLocked));
+        for _ in 0..100 {
+            engine.update(input, 1.0);
+        }
+        assert!(engine.stats.pieces > 5);
+    }
+
+    /// The I block in `rotation` (its SRS state being one behind) at `x`, `y`.
+    fn place_i(engine: &mut Engine, rotation: u8, x: f32, y: f32) {
+        engine.block = Block {
+            rotation,
+            position: Vec2::new(x, y),
+            ..engine.spawn_block(BlockShape::I)
+        };
+    }
+
+    #[test]
+    fn t_spin_triple_takes_the_last_kick() {
+        let mut engine = new_engine(GameMode::Marathon);
+        let height = engine.board.height() as i32;
+        for y in height - 3..height {
+            for x in 0..engine.board.width() as i32 {
+                engine.board.set(x, y, GARBAGE);
+            }
+        }
+        // The slot the T ends up in, standing on its right side, under an
+        // overhang that stops it dropping straight in.
+        for (x, y) in [(1, height - 3), (1, height - 2), (2, height - 2), (1, height - 1)] {
+            engine.board.set(x, y, 0);
+        }
+        engine.board.set(1, height - 5, GARBAGE);
+        engine.block = Block {
+            position: Vec2::new(1.0, (height - 5) as f32),
+            ..engine.spawn_block(BlockShape::T)
+        };
+
+        assert!(engine.rotate(Rotation::Clockwise));
+        // SRS's fifth 0 -> R test: one left and two down.
+        assert_eq!(engine.block.position, Vec2::new(0.0, (height - 3) as f32));
+        let events = engine.update(hard_drop(), 1.0);
+        assert!(events.contains(&Event::LinesCleared(3)));
+        assert_eq!(engine.stats.t_spins, 1);
+    }
+
+    #[test]
+    fn i_kicks_from_the_spawn_state() {
+        // Flat on the floor, standing up needs the fifth test: right one, up
+        // two.
+        let mut engine = new_engine(GameMode::Marathon);
+        let floor = engine.board.height() as f32;
+        place_i(&mut engine, 1, 3.0, floor - 2.0);
+        assert!(engine.rotate(Rotation::Clockwise));
+        assert_eq!(engine.block.position, Vec2::new(4.0, floor - 4.0));
+    }
+
+    #[test]
+    fn i_kicks_from_the_right_state() {
+        // Upright against the right wall, lying down needs the second test:
+        // left one.
+        let mut engine = new_engine(GameMode::Marathon);
+        place_i(&mut engine, 2, 7.0, 10.0);
+        assert!(engine.rotate(Rotation::Clockwise));
+        assert_eq!(engine.block.position, Vec2::new(6.0, 10.0));
+    }
+
+    #[test]
+    fn i_kicks_from_the_two_state() {
+        // Flat on the floor again, from the lower row: the fourth test, right
+        // two and up one.
+        let mut engine = new_engine(GameMode::Marathon);
+        let floor = engine.board.height() as f32;
+        place_i(&mut engine, 3, 3.0, floor - 3.0);
+        assert!(engine.rotate(Rotation::Clockwise));
+        assert_eq!(engine.block.position, Vec2::new(5.0, floor - 4.0));
+    }
+
+    #[test]
+    fn i_kicks_from_the_left_state() {
+        // Upright against the left wall: the second test, right one.
+        let mut engine = new_engine(GameMode::Marathon);
+        place_i(&mut engine, 0, -1.0, 10.0);
+        assert!(engine.rotate(Rotation::Clockwise));
+        assert_eq!(engine.block.position, Vec2::new(0.0, 10.0));
+    }
+
+    #[test]
+    fn guideline_gravity_stops_speeding_up_at_20g() {
+        let mut engine = new_engine(GameMode::Marathon);
+        engine.score.level = GUIDELINE_TOP_LEVEL;
+        let top_speed = engine.gravity_delay();
+        assert!(top_speed > 0.0 && top_speed < 1.0 / 20.0);
+        for level in [115, 260, 1000] {
+            engine.score.level = level;
+            assert_eq!(engine.gravity_delay(), top_speed);
+        }
+    }
+
+    #[test]
+    fn spawning_into_the_stack_is_a_block_out() {
+        let mut engine = new_engine(GameMode::Marathon);
+        engine.block = engine.spawn_block(BlockShape::T);
+        // The stack reaches the visible top, so the T lands half in the
+        // hidden rows and the next block has nowhere to go.
+        let visible = engine.board.visible_height();
+        fill_rows(&mut engine, visible - 1);
+        let events = engine.update(hard_drop(), 1.0);
+        assert!(events.contains(&Event::GameOver));
+        assert!(engine.game_over);
+        assert!(!engine.finished);
+    }
+
+    #[test]
+    fn locking_above_the_board_is_a_lock_out() {
+        let mut engine = new_engine(GameMode::Marathon);
+        engine.block = engine.spawn_block(BlockShape::T);
+        let visible = engine.board.visible_height();
+        fill_rows(&mut engine, visible);
+        let events = engine.update(hard_drop(), 1.0);
+        assert!(events.contains(&Event::GameOver));
+        assert!(!events.contains(&Event::Spawned));
+    }
+
+    #[test]
+    fn zen_compresses_instead_of_topping_out() {
+        let mut engine = new_engine(GameMode::Zen);
+        engine.block = engine.spawn_block(BlockShape::T);
+        let visible = engine.board.visible_height();
+        fill_rows(&mut engine, visible);
+        let events = engine.update(hard_drop(), 1.0);
+        assert!(events.contains(&Event::Compressed));
+        assert!(!engine.game_over);
+        assert_eq!(engine.stats.compressions, 1);
+    }
+
+    #[test]
+    fn garbage_pushed_through_the_top_is_a_game_over() {
+        let mut engine = new_engine(GameMode::Dig(10, Messiness::Messy));
+        engine.board.remove_bottom_rows(1);
+        engine.board.set(0, 0, 1);
+        let mut events = Vec::new();
+        engine.refill_garbage(&mut events);
+        assert!(events.contains(&Event::GameOver));
+        assert!(engine.game_over);
+    }
+
+    fn tiny_zen() -> Engine {
+        let rules = Rules {
+            board_width: 4,
+            board_height: 1,
+            ..GameMode::Zen.rules()
+        };
+        Engine::new(rules, Handling::default(), SEED)
+    }
+
+    #[test]
+    fn compressing_a_one_row_board_makes_room() {
+        let mut engine = tiny_zen();
+        for y in 0..engine.board.height() {
+            for x in 0..engine.board.width() {
+                engine.board.set(x as i32, y as i32, 1);
+            }
+        }
+        let mut events = Vec::new();
+        engine.check_block_out(&mut events);
+        assert!(!engine.game_over);
+        assert!(!engine.block_collides());
+        assert!(engine.stats.compressions > 0);
+    }
+
+    #[test]
+    fn a_block_that_never_fits_ends_a_compressing_game() {
+        let mut engine = tiny_zen();
+        // Nothing compressing does can move it back inside the walls.
+        engine.block.position.x = -3.0;
+        let mut events = Vec::new();
+        engine.check_block_out(&mut events);
+        assert!(events.contains(&Event::GameOver));
+        assert!(engine.game_over);
+    }
+
+    /// A few thousand ticks of mashing, the same every time.
+    fn mashing(ticks: usize) -> Vec<Input> {
+        let rng = seeded_rng(SEED);
+        let mut held = Buttons::default();
+        (0..ticks).map(|_| {
+            let last = held;
+            let bit = || rng.gen_range(0, 8) == 0;
+            held = Buttons {
+                left: bit(),
+                right: bit(),
+                soft_drop: bit(),
+                hard_drop: bit(),
+                rotate_cw: bit(),
+                rotate_ccw: bit(),
+                rotate_180: bit(),
+                hold: bit(),
+            };
+            Input {
+                held,
+                pressed: Buttons {
+                    left: held.left && !last.left,
+                    right: held.right && !last.right,
+                    soft_drop: held.soft_drop && !last.soft_drop,
+                    hard_drop: held.hard_drop && !last.hard_drop,
+                    rotate_cw: held.rotate_cw && !last.rotate_cw,
+                    rotate_ccw: held.rotate_ccw && !last.rotate_ccw,
+                    rotate_180: held.rotate_180 && !last.rotate_180,
+                    hold: held.hold && !last.hold,
+                },
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_play_the_same_game() {
+        let inputs = mashing(3000);
+        for mode in [GameMode::Marathon, GameMode::Master, GameMode::Dig(10, Messiness::Messy), GameMode::Zen] {
+            let mut first = new_engine(mode);
+            let mut second = new_engine(mode);
+            for &input in inputs.iter() {
+                assert_eq!(first.update(input, 1.0), second.update(input, 1.0));
+            }
+            assert_eq!(first.board, second.board);
+            assert_eq!(first.block, second.block);
+            assert_eq!(first.queue, second.queue);
+            assert_eq!(first.score, second.score);
+            assert_eq!(first.stats, second.stats);
+            assert!(first.stats.pieces > 5, "{:?} only placed {}", mode, first.stats.pieces);
+        }
+    }
+
+    #[test]
+    fn master_splits_when_a_clear_ends_the_section_stop() {
+        let mut engine = new_engine(GameMode::Master);
+        engine.score.level = 99;
+        for _ in 0..100 {
+            engine.update(Input::default(), 1.0);
+        }
+        assert_eq!(engine.score.level, 99);
+        assert!(engine.splits.is_empty());
+
+        // A flat I fills the gap in the bottom row.
+        engine.board = Board::new(engine.rules.board_width, engine.rules.board_height, HIDDEN_ROWS);
+        engine.spawn_delay = 0.0;
+        engine.block = engine.spawn_block(BlockShape::I);
+        let bottom = engine.board.height() - 1;
+        let x = engine.block.position.x as usize;
+        for column in (0..engine.board.width()).filter(|column| !(x..x + 4).contains(column)) {
+            engine.board.set(column as i32, bottom as i32, GARBAGE);
+        }
+        let frames = engine.frames;
+        engine.update(hard_drop(), 1.0);
+        assert_eq!(engine.score.level, 100);
+        assert_eq!(engine.splits, [frames + 1.0]);
+        assert_eq!(engine.section_grades.len(), 1);
+    }
+
+    #[test]
+    fn master_records_the_last_section_on_finishing() {
+        let mut engine = new_engine(GameMode::Master);
+        engine.score.level = 950;
+        engine.update(Input::default(), 1.0);
+        assert_eq!(engine.splits.len(), 9);
+        engine.score.level = MAX_TGM_LEVEL;
+        engine.update(Input::default(), 1.0);
+        assert!(engine.finished);
+        assert_eq!(engine.splits.len(), 10);
+        assert_eq!(engine.section_grades.len(), 10);
+    }
+}
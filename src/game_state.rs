@@ -1,203 +1,361 @@
 use macroquad::audio::stop_sound;
-use macroquad::audio::play_sound;
-use macroquad::audio::PlaySoundParams;
 use crate::COLORS;
-use crate::Block;
-use crate::util::clamp_range;
+use crate::controls::Action;
+use crate::engine::Buttons;
+use crate::high_scores::ScoreEntry;
+use crate::high_scores::MAX_NAME_LENGTH;
+use crate::mode::Goal;
+use crate::mode::GameMode;
+use crate::mode::Ranking;
+use crate::engine::SPLIT_LINES;
+use crate::scoring::GRADES;
+use crate::util::format_time;
+use crate::util::format_time_precise;
+use crate::pause_state::leave_game;
+use crate::pause_state::pause;
+use crate::sprites::render_block;
+use crate::sprites::render_ghost;
+use crate::sprites::render_lock;
+use crate::layout::held_position;
+use crate::layout::queue_position;
+use crate::layout::QUEUE_SCALE;
+use crate::block::Block;
+use crate::line_clear::clearing_cell;
+use crate::line_clear::render_line_clears;
+use crate::line_clear::start_line_clear;
+use crate::line_clear::update_line_clears;
+use crate::scores_state::render_score_table;
+use crate::util::today;
+use crate::engine::Event;
+use crate::engine::Input;
 use macroquad::prelude::*;
 use crate::Game;
-use crate::util::delta_time;
 use crate::GameState;
 
-pub fn update_game(game: &mut Game) -> bool {
-    game.block.lerp_position();
-    game.next_block.lerp_position();
-    if !game.held_block.is_none() {
-        let held = game.held_block.unwrap();
-        game.held_block.get_or_insert_with(|| held).lerp_position();
-    }
-    if game.game_over {
-        if is_key_pressed(KeyCode::X) {
-            game.shake();
-            *game = Game {
-                state: GameState::Game,
-                placed_blocks: [[8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8]; 16],
-                block: Block {
-                    position: vec2(5.0, 0.0),
-                    ..Default::default()
-                },
-                game_over: false,
-                next_block: Block::default(),
-                held_block: None,
+/// A frame this long (in seconds) means the window was minimized or hidden and
+/// stopped getting frames, which is treated as losing focus.
+const FOCUS_LOST_FRAME_TIME: f32 = 0.5;
 
-                particles: game.particles.clone(),
+fn read_buttons(game: &Game, check: fn(&Game, Action) -> bool) -> Buttons {
+    Buttons {
+        left: check(game, Action::Left),
+        right: check(game, Action::Right),
+        soft_drop: check(game, Action::SoftDrop),
+        hard_drop: check(game, Action::HardDrop),
+        rotate_cw: check(game, Action::RotateCw),
+        rotate_ccw: check(game, Action::RotateCcw),
+        rotate_180: check(game, Action::Rotate180),
+        hold: check(game, Action::Hold),
+    }
+}
 
-                played_game_over: false,
-                ..*game
-            };
+/// Handles everything that isn't the game itself: game over and pausing.
+/// The game moves on in `tick_game`.
+pub fn update_game(game: &mut Game) {
+    if game.engine.game_over {
+        if game.name_entry.is_some() {
+            update_name_entry(game);
+        } else if game.is_pressed(Action::Restart) {
+            game.new_game();
+            game.shake();
             stop_sound(game.game_over_sfx.unwrap());
-            play_sound(
-                game.music.unwrap(),
-                PlaySoundParams {
-                    looped: true,
-                    volume: 0.4,
-                },
-            );
-            return true;
+            game.play_music();
+        } else if game.is_pressed(Action::Back)
+        || game.is_pressed(Action::Pause) {
+            stop_sound(game.game_over_sfx.unwrap());
+            leave_game(game);
         }
-        return false;
+        return;
     }
-    if is_key_pressed(KeyCode::Z) {
-        game.block.rotation += 1;
-        if game.block.rotation > 3 {
-            game.block.rotation = 0;
-        }
-        if game.block_collides() {
-            if game.block.rotation == 0 {
-                game.block.rotation = 3;
-            } else {
-                game.block.rotation -= 1;
-            }
-        }
+
+    if game.is_pressed(Action::Pause)
+    || get_frame_time() > FOCUS_LOST_FRAME_TIME {
+        pause(game);
+        return;
     }
-    if is_key_pressed(KeyCode::X) {
-        game.shake();
-        for _ in game.block.position.y as usize..16 {
-            game.block.position.y += 1.0;
-            if game.block_collides() {
-                game.block.position.y -= 1.0;
-                game.block.gravity_timer = 0.0;
-                game.block.movement_timer = 6.0;
-                break;
-            }
-        }
+
+    game.pressed_buttons = game.pressed_buttons.union(read_buttons(game, Game::is_pressed));
+}
+
+/// Runs the game for a tick, with the buttons pressed since the last one.
+pub fn tick_game(game: &mut Game) {
+    game.sprites.ease(&game.engine);
+    update_line_clears(game);
+    if game.engine.game_over {
+        return;
     }
-    if is_key_pressed(KeyCode::C)
-    && !game.has_switched {
-        game.has_switched = true;
-        game.shake();
-        play_sound(
-            game.hit_sfx.unwrap(),
-            PlaySoundParams {
-                looped: false,
-                volume: 0.85,
-            },
-        );
-        let old_held_block = game.held_block.clone();
-        game.held_block = Some(Block {
-            position: vec2(13.0, 7.0),
-            ..game.block
-        });
-        game.block = Block {
-            position: vec2(5.0, 0.0),
-            ..if old_held_block.is_none() {
-                game.next_block
-            } else {
-                old_held_block.unwrap()
-            }
-        };
-        if old_held_block.is_none() {
-            game.next_block = Block::default();
-        }
+    let input = Input {
+        held: read_buttons(game, Game::is_down),
+        pressed: game.pressed_buttons,
+    };
+    game.pressed_buttons = Buttons::default();
+    // Each tick is exactly one frame, at any refresh rate.
+    game.recording.record(input, 1.0);
+    let events = game.engine.update(input, 1.0);
+    for event in events {
+        handle_event(game, event);
     }
-    if is_key_down(KeyCode::Left)
-    || is_key_down(KeyCode::Right) {
-        game.block.movement_timer -= delta_time();
-        if game.block.movement_timer <= 0.0 {
-            game.block.movement_timer = 7.0;
-            game.block.position.x += if is_key_down(KeyCode::Left) { -1.0 } else { 1.0 };
-            if game.block_collides() {
-                game.block.position.x -= if is_key_down(KeyCode::Left) { -1.0 } else { 1.0 };
-            }
-        }
-    } else {
-        game.block.movement_timer = 0.0;
+}
+
+fn score_entry(game: &Game, name: String) -> ScoreEntry {
+    let engine = &game.engine;
+    ScoreEntry {
+        name,
+        score: engine.score.score,
+        lines: engine.score.lines,
+        level: engine.score.level,
+        time: (engine.frames / 60.0) as f32,
+        date: today(),
+        splits: engine.splits.iter().map(|&frames| (frames / 60.0) as f32).collect(),
+        grade: match engine.rules.goal {
+            Goal::Level(_) => Some(GRADES[engine.grade()].to_string()),
+            _ => None,
+        },
+    }
+}
+
+/// Starts name entry if the game that just ended made the high score table.
+fn check_for_record(game: &mut Game) {
+    if !game.mode.has_high_scores() {
+        return;
+    }
+    if game.mode.ranking() == Ranking::Time
+    && !game.engine.finished {
+        return;
+    }
+    if game.high_scores.rank(game.mode, &score_entry(game, String::new())).is_some() {
+        // Throw away anything typed during the game.
+        while get_char_pressed().is_some() {}
+        game.name_entry = Some(game.high_scores.last_name.clone());
     }
+}
 
-    game.block.gravity_timer -= delta_time();
-    if game.block.gravity_timer <= 0.0
-    || is_key_pressed(KeyCode::Down) {
-        if is_key_down(KeyCode::Down) {
-            game.block.gravity_timer = 5.0;
-        } else {
-            game.block.gravity_timer = 45.0;
+/// Typing in a name for the high score table, after a game good enough to
+/// make it in.
+fn update_name_entry(game: &mut Game) {
+    let name = game.name_entry.as_mut().unwrap();
+    while let Some(character) = get_char_pressed() {
+        if (character.is_alphanumeric() || character == ' ')
+        && name.chars().count() < MAX_NAME_LENGTH {
+            name.push(character);
         }
-        game.block.position.y += 1.0;
+    }
+    if is_key_pressed(KeyCode::Backspace) {
+        name.pop();
+    }
+    // Not the keyboard's confirm keys, which might be letters in the name,
+    // but a gamepad can't type anyway.
+    if is_key_pressed(KeyCode::Enter)
+    || is_key_pressed(KeyCode::KpEnter)
+    || game.gamepads.is_pressed(Action::Confirm) {
+        let name = match name.trim() {
+            "" => "Player".to_string(),
+            name => name.to_string(),
+        };
+        let entry = score_entry(game, name);
+        game.new_record = game.high_scores.insert(game.mode, entry);
+        game.name_entry = None;
+        game.save_high_scores();
+    }
+}
 
-        let shape = game.block.get_shape();
-        if game.block_collides() {
-            game.has_switched = false;
+fn handle_event(game: &mut Game, event: Event) {
+    game.sprites.handle_event(&game.engine, event);
+    match event {
+        Event::HardDrop => game.shake(),
+        Event::LinesCleared(lines) => start_line_clear(game, lines),
+        Event::Compressed => {
             game.shake();
-            play_sound(
-                game.hit_sfx.unwrap(),
-                PlaySoundParams {
-                    looped: false,
-                    volume: 0.85,
-                },
-            );
-            game.block.position.y -= 1.0;
-            for y in 0..shape.len() {
-                for x in 0..shape[y].len() {
-                    if shape[y][x] != 0 {
-                        game.placed_blocks[y + clamp_range(0.0, game.block.position.y, 20.0) as usize][x + clamp_range(0.0, game.block.position.x, 20.0) as usize] = shape[y][x];
-                    }
-                }   
-            }
-            game.block = Block {
-                position: vec2(5.0, 0.0),
-                block_shape: game.next_block.block_shape,
-                ..Default::default()
-            };
-            game.next_block = Block::default();
-        }
+            game.play_effect(game.game_over_sfx, 0.3);
+        },
+        Event::Hold
+        | Event::PieceLocked => {
+            game.shake();
+            game.play_effect(game.hit_sfx, 0.85);
+        },
+        Event::LevelUp(_) => {
+            game.play_effect(game.play_sfx, 0.6);
+        },
+        Event::GameOver => {
+            game.save_replay();
+            stop_sound(game.music.unwrap());
+            game.play_effect(game.game_over_sfx, 0.8);
+            check_for_record(game);
+        },
+        Event::Finished => {
+            game.save_replay();
+            stop_sound(game.music.unwrap());
+            game.shake();
+            game.play_effect(game.play_sfx, 0.9);
+            check_for_record(game);
+        },
+        Event::Spawned => {},
     }
+}
 
-    let mut is_full_line = true;
-    for y in 0..game.placed_blocks.len() {
-        is_full_line = true;
-        for x in 1..game.placed_blocks[y].len() - 1 {
-            if game.placed_blocks[y][x] == 0 {
-                is_full_line = false;
-            }
-        }
-        if is_full_line {
-            for i in (1..=y).rev() {
-                game.placed_blocks[i] = game.placed_blocks[i - 1];
+pub fn render_score(game: &Game) {
+    if game.mode == GameMode::Zen
+    && game.settings.visuals.zen_music_only {
+        return;
+    }
+    let engine = &game.engine;
+    let score = &engine.score;
+    let x = game.layout().text_x();
+    match engine.rules.goal {
+        Goal::Lines(target) => {
+            render_splits(game);
+            draw_text(&format!("Time: {}", format_time_precise((engine.frames / 60.0) as f32)), x, 188.0, 16.0, WHITE);
+            draw_text(&format!("Lines: {}/{}", score.lines, target), x, 202.0, 16.0, WHITE);
+        },
+        Goal::Garbage(rows) => {
+            render_splits(game);
+            draw_text(&format!("Time: {}", format_time_precise((engine.frames / 60.0) as f32)), x, 188.0, 16.0, WHITE);
+            draw_text(&format!("Garbage: {}/{}", engine.garbage_cleared, rows), x, 202.0, 16.0, WHITE);
+            draw_text(&format!("Lines: {}", score.lines), x, 216.0, 16.0, WHITE);
+        },
+        Goal::Level(_) => {
+            render_grades(game);
+            render_splits(game);
+            draw_text(&format!("Score: {}", score.score), x, 188.0, 16.0, WHITE);
+            draw_text(&format!("Level: {}/{}", score.level, score.section_stop() + 1), x, 202.0, 16.0, WHITE);
+            draw_text(&format!("Time: {}", format_time((engine.frames / 60.0) as f32)), x, 216.0, 16.0, WHITE);
+        },
+        _ => {
+            if let Goal::Time(seconds) = engine.rules.goal {
+                let left = (seconds as f32 - (engine.frames / 60.0) as f32).max(0.0);
+                let color = if left < 10.0 { COLORS[7] } else { WHITE };
+                draw_text(&format!("Left: {}", format_time(left)), x, 170.0, 16.0, color);
             }
-        }
+            draw_text(&format!("Score: {}", score.score), x, 188.0, 16.0, WHITE);
+            draw_text(&format!("Level: {}", score.level), x, 202.0, 16.0, WHITE);
+            draw_text(&format!("Lines: {}", score.lines), x, 216.0, 16.0, WHITE);
+        },
+    }
+    if score.combo > 0 {
+        draw_text(&format!("Combo x{}", score.combo), x, 234.0, 16.0, COLORS[1]);
+    }
+    if score.back_to_back {
+        draw_text("Back-to-back", x, 248.0, 16.0, COLORS[2]);
+    }
+}
+
+/// The latest split, and how far ahead (negative) or behind it is compared
+/// to the personal best.
+fn render_splits(game: &Game) {
+    let Some(&frames) = game.engine.splits.last() else {
+        return;
+    };
+    let x = game.layout().text_x();
+    let split = (frames / 60.0) as f32;
+    let index = game.engine.splits.len() - 1;
+    let label = match game.engine.rules.goal {
+        Goal::Level(_) => format!("Lv{}", (index + 1) * 100),
+        _ => format!("{}L", (index + 1) as u32 * SPLIT_LINES),
+    };
+    draw_text(&format!("{}: {}", label, format_time(split)), x, 156.0, 16.0, WHITE);
+    let best = game.high_scores.personal_best(game.mode).and_then(|entry| entry.splits.get(index));
+    if let Some(&best) = best {
+        let difference = split - best;
+        let color = if difference <= 0.0 { COLORS[1] } else { COLORS[7] };
+        draw_text(&format!("{:+.2}", difference), x, 170.0, 16.0, color);
     }
-    if is_full_line {
-        game.shake();
+}
+
+/// The current grade, and the grade held at the end of each section so far.
+fn render_grades(game: &Game) {
+    let x = game.layout().text_x();
+    draw_text(&format!("Grade: {}", GRADES[game.engine.grade()]), x, 110.0, 16.0, COLORS[1]);
+    for (i, &grade) in game.engine.section_grades.iter().enumerate() {
+        draw_text(GRADES[grade], x + (i % 5) as f32 * 20.0, 126.0 + (i / 5) as f32 * 12.0, 12.0, WHITE);
     }
+}
 
-    for x in 1..game.placed_blocks[0].len() - 1 {
-        if game.placed_blocks[0][x] != 0 {
-            return false;
+/// Draws the walls and the well, with the placed blocks in it if `blocks` is
+/// set.
+pub fn render_board(game: &Game, blocks: bool) {
+    let layout = game.layout();
+    let board = &game.engine.board;
+    let texture = game.block_texture.unwrap();
+    for (y, row) in board.rows().enumerate().skip(board.hidden_rows()) {
+        let clearing = blocks
+            && game.engine.is_clearing()
+            && game.engine.cleared_rows.iter().any(|&(cleared, _)| cleared == y);
+        let y = y as f32;
+        layout.draw_cell(texture, vec2(-1.0, y), COLORS[8]);
+        layout.draw_cell(texture, vec2(board.width() as f32, y), COLORS[8]);
+        for (x, &cell) in row.iter().enumerate() {
+            let color = if blocks { COLORS[cell as usize] } else { COLORS[0] };
+            if clearing {
+                let (color, scale) = clearing_cell(game, x, color);
+                layout.draw_cell(texture, vec2(x as f32, y), COLORS[0]);
+                let offset = (1.0 - scale) / 2.0;
+                layout.draw_scaled_cell(texture, vec2(x as f32 + offset, y + offset), scale, color);
+            } else {
+                layout.draw_cell(texture, vec2(x as f32, y), color);
+            }
         }
     }
-    true
 }
 
 pub fn render_game(game: &Game) {
-    for y in 0..game.placed_blocks.len() {
-        for x in 0..game.placed_blocks[y].len() {
-            draw_texture(
-                game.block_texture.unwrap(),
-                x as f32 * 16.0,
-                y as f32 * 16.0,
-                COLORS[game.placed_blocks[y][x] as usize]
-            );
+    let engine = &game.engine;
+    let layout = game.layout();
+    let alpha = game.tick_alpha();
+    render_board(game, true);
+    // Between blocks, the last one is already part of the board.
+    if engine.spawn_delay <= 0.0 {
+        let position = game.sprites.block.at(alpha);
+        if !engine.game_over {
+            render_ghost(game, &engine.block, position.x, engine.landing_y(), game.settings.visuals.ghost_style);
         }
+        render_block(game, &engine.block, position, 1.0);
+        render_lock(game, &engine.block, position, engine.lock_progress());
+    }
+    render_line_clears(game);
+    for (i, (&shape, motion)) in engine.queue.iter().zip(game.sprites.queue.iter()).enumerate() {
+        let position = motion.at(alpha);
+        render_block(game, &Block::new(shape, position), position, if i == 0 { 1.0 } else { QUEUE_SCALE });
+    }
+    if let Some(shape) = engine.held {
+        let position = game.sprites.held.at(alpha);
+        render_block(game, &Block::new(shape, position), position, 1.0);
     }
-    game.block.render(game);
-    game.next_block.render(game);
-    if let Some(block) = game.held_block {
-        block.render(game);
+    let next_label = layout.screen_position(queue_position(&engine.board, 0));
+    draw_text("Next:", next_label.x, next_label.y - 4.0, 16.0, WHITE);
+    if engine.rules.hold {
+        let held_label = layout.screen_position(held_position(&engine.board));
+        draw_text("Held:", held_label.x, held_label.y - 4.0, 16.0, WHITE);
     }
-    draw_text("Next:", 215.0, 12.0, 16.0, WHITE);
-    draw_text("Held:", 215.0, 92.0, 16.0, WHITE);
-    if game.game_over {
-        draw_text("GAME OVER!", 28.0, 25.0, 32.0, WHITE);
-        draw_text("X to play again?", 40.0, 41.0, 16.0, WHITE);
+    render_score(game);
+    if engine.game_over {
+        let title = match engine.rules.goal {
+            _ if !engine.finished => "GAME OVER!",
+            Goal::Time(_) => "TIME UP!",
+            _ => "FINISHED!",
+        };
+        draw_text(title, 28.0, 25.0, 32.0, WHITE);
+        // A replay shows how the game ended, but it's not the player's turn.
+        if game.state == GameState::Playback {
+            return;
+        }
+        draw_rectangle(4.0, 48.0, layout.side_x - 8.0, 170.0, Color { a: 0.85, ..COLORS[9] });
+        if let Some(name) = &game.name_entry {
+            draw_text("New high score!", 40.0, 41.0, 16.0, COLORS[1]);
+            draw_text("Enter your name:", 12.0, 68.0, 16.0, WHITE);
+            let cursor = if (game.time * 2.0) as i32 % 2 == 0 { "_" } else { "" };
+            draw_text(&format!("{}{}", name, cursor), 12.0, 88.0, 16.0, COLORS[1]);
+        } else {
+            draw_text(&format!("{} to play again?", game.settings.bindings.describe(Action::Restart)), 40.0, 41.0, 16.0, WHITE);
+            if game.new_record == Some(0) {
+                draw_text("NEW RECORD!", 12.0, 64.0, 16.0, COLORS[1]);
+            }
+            if game.mode.has_high_scores() {
+                render_score_table(game, game.mode, 12.0, 82.0, true, game.new_record);
+            }
+        }
+        draw_text(&format!("Seed: {}", engine.seed), 12.0, 232.0, 16.0, WHITE);
+        if game.name_entry.is_none() {
+            draw_text(&format!("{}: menu", game.settings.bindings.describe(Action::Back)), 12.0, 248.0, 16.0, WHITE);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+use gilrs::Axis;
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+use gilrs::Button;
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+use gilrs::Gilrs;
+use crate::controls::Action;
+
+/// How far a stick has to be pushed to count as a d-pad press.
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+const STICK_DEADZONE: f32 = 0.5;
+
+/// Every connected gamepad, merged together. Buttons have a fixed layout, and
+/// the left stick works like the d-pad. There's no gamepad support on the web,
+/// or without the `gamepad` feature.
+pub struct Gamepads {
+    #[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+    gilrs: Option<Gilrs>,
+    down: HashSet<Action>,
+    pressed: HashSet<Action>,
+}
+
+impl Gamepads {
+    pub fn new() -> Gamepads {
+        Gamepads {
+            #[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+            gilrs: Gilrs::new().ok(),
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+        }
+    }
+
+    /// Reads the gamepads. Call this once at the start of every frame.
+    #[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+    pub fn update(&mut self) {
+        let Some(gilrs) = self.gilrs.as_mut() else {
+            return;
+        };
+        while gilrs.next_event().is_some() {}
+
+        let mut down = HashSet::new();
+        for (_, gamepad) in gilrs.gamepads() {
+            let stick_x = gamepad.value(Axis::LeftStickX);
+            let stick_y = gamepad.value(Axis::LeftStickY);
+            let is_down = |button: Button| gamepad.is_pressed(button) || match button {
+                Button::DPadLeft => stick_x < -STICK_DEADZONE,
+                Button::DPadRight => stick_x > STICK_DEADZONE,
+                Button::DPadUp => stick_y > STICK_DEADZONE,
+                Button::DPadDown => stick_y < -STICK_DEADZONE,
+                _ => false,
+            };
+            for action in Action::ALL {
+                if buttons(action).iter().any(|&button| is_down(button)) {
+                    down.insert(action);
+                }
+            }
+        }
+        self.pressed = down.difference(&self.down).copied().collect();
+        self.down = down;
+    }
+
+    #[cfg(not(all(feature = "gamepad", not(target_arch = "wasm32"))))]
+    pub fn update(&mut self) {}
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.down.contains(&action)
+    }
+
+    /// Whether `action`'s button went down this frame.
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+}
+
+#[cfg(all(feature = "gamepad", not(target_arch = "wasm32")))]
+fn buttons(action: Action) -> &'static [Button] {
+    match action {
+        Action::Left => &[Button::DPadLeft],
+        Action::Right => &[Button::DPadRight],
+        Action::SoftDrop => &[Button::DPadDown],
+        Action::HardDrop => &[Button::DPadUp],
+        Action::RotateCw => &[Button::South],
+        Action::RotateCcw => &[Button::East],
+        Action::Rotate180 => &[Button::North],
+        Action::Hold => &[Button::LeftTrigger, Button::RightTrigger],
+        Action::Restart => &[Button::Start],
+        Action::Pause => &[Button::Start],
+        Action::MenuUp => &[Button::DPadUp],
+        Action::MenuDown => &[Button::DPadDown],
+        Action::MenuLeft => &[Button::DPadLeft],
+        Action::MenuRight => &[Button::DPadRight],
+        Action::Confirm => &[Button::South, Button::Start],
+        Action::Back => &[Button::East, Button::Select],
+    }
+}
@@ -0,0 +1,179 @@
+use macroquad::rand::ChooseRandom;
+use macroquad::rand::RandGenerator;
+use crate::block::BlockShape;
+use crate::util::seeded_rng;
+
+/// Decides which piece comes next.
+pub trait PieceGenerator {
+    fn next_shape(&mut self) -> BlockShape;
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GeneratorKind {
+    SevenBag,
+    FourteenBag,
+    Random,
+    History,
+}
+
+impl GeneratorKind {
+    pub fn create(&self, seed: u64) -> Box<dyn PieceGenerator> {
+        let rng = seeded_rng(seed);
+        match self {
+            GeneratorKind::SevenBag => Box::new(BagGenerator::new(1, rng)),
+            GeneratorKind::FourteenBag => Box::new(BagGenerator::new(2, rng)),
+            GeneratorKind::Random => Box::new(RandomGenerator::new(rng)),
+            GeneratorKind::History => Box::new(HistoryGenerator::new(rng)),
+        }
+    }
+}
+
+/// Deals out shuffled bags holding `copies` of every piece, so the same piece
+/// can never be too far apart or repeat too often.
+pub struct BagGenerator {
+    copies: usize,
+    bag: Vec<BlockShape>,
+    rng: RandGenerator,
+}
+
+impl BagGenerator {
+    pub fn new(copies: usize, rng: RandGenerator) -> BagGenerator {
+        BagGenerator {
+            copies,
+            bag: Vec::new(),
+            rng,
+        }
+    }
+}
+
+impl PieceGenerator for BagGenerator {
+    fn next_shape(&mut self) -> BlockShape {
+        if self.bag.is_empty() {
+            for _ in 0..self.copies {
+                self.bag.extend_from_slice(&BlockShape::ALL);
+            }
+            self.bag.shuffle_with_state(&self.rng);
+        }
+        self.bag.pop().unwrap()
+    }
+}
+
+/// Every piece is equally likely every time, like the original games.
+pub struct RandomGenerator {
+    rng: RandGenerator,
+}
+
+impl RandomGenerator {
+    pub fn new(rng: RandGenerator) -> RandomGenerator {
+        RandomGenerator {
+            rng,
+        }
+    }
+}
+
+impl PieceGenerator for RandomGenerator {
+    fn next_shape(&mut self) -> BlockShape {
+        BlockShape::ALL[self.rng.gen_range(0, BlockShape::ALL.len())]
+    }
+}
+
+/// TGM style: rerolls a few times if the piece was one of the last four, and
+/// never starts with an S, Z or O.
+pub struct HistoryGenerator {
+    history: [BlockShape; 4],
+    first: bool,
+    rng: RandGenerator,
+}
+
+impl HistoryGenerator {
+    const ROLLS: usize = 6;
+
+    pub fn new(rng: RandGenerator) -> HistoryGenerator {
+        HistoryGenerator {
+            history: [BlockShape::Z, BlockShape::S, BlockShape::S, BlockShape::Z],
+            first: true,
+            rng,
+        }
+    }
+}
+
+impl PieceGenerator for HistoryGenerator {
+    fn next_shape(&mut self) -> BlockShape {
+        let shape = if self.first {
+            self.first = false;
+            [BlockShape::I, BlockShape::T, BlockShape::J, BlockShape::L][self.rng.gen_range(0, 4)]
+        } else {
+            let mut shape = BlockShape::ALL[self.rng.gen_range(0, BlockShape::ALL.len())];
+            for _ in 1..HistoryGenerator::ROLLS {
+                if !self.history.contains(&shape) {
+                    break;
+                }
+                shape = BlockShape::ALL[self.rng.gen_range(0, BlockShape::ALL.len())];
+            }
+            shape
+        };
+        self.history.rotate_right(1);
+        self.history[0] = shape;
+        shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 7;
+
+    fn deal(generator: &mut dyn PieceGenerator, count: usize) -> Vec<BlockShape> {
+        (0..count).map(|_| generator.next_shape()).collect()
+    }
+
+    fn assert_bags(kind: GeneratorKind, copies: usize) {
+        let mut generator = kind.create(SEED);
+        let bag_size = BlockShape::ALL.len() * copies;
+        for bag in deal(generator.as_mut(), bag_size * 20).chunks(bag_size) {
+            for shape in BlockShape::ALL {
+                assert_eq!(bag.iter().filter(|&&other| other == shape).count(), copies, "{:?} in {:?}", shape, bag);
+            }
+        }
+    }
+
+    #[test]
+    fn seven_bag_deals_every_shape_once_a_bag() {
+        assert_bags(GeneratorKind::SevenBag, 1);
+    }
+
+    #[test]
+    fn fourteen_bag_deals_every_shape_twice_a_bag() {
+        assert_bags(GeneratorKind::FourteenBag, 2);
+    }
+
+    #[test]
+    fn history_only_repeats_once_its_rolls_run_out() {
+        let mut generator = HistoryGenerator::new(seeded_rng(SEED));
+        // The same stream of rolls the generator sees.
+        let rolls = seeded_rng(SEED);
+        let first = generator.next_shape();
+        rolls.gen_range(0, 4);
+        assert!(![BlockShape::S, BlockShape::Z, BlockShape::O].contains(&first));
+
+        let mut history = [first, BlockShape::Z, BlockShape::S, BlockShape::S];
+        for _ in 0..500 {
+            let shape = generator.next_shape();
+            let mut rolled = Vec::new();
+            for _ in 0..HistoryGenerator::ROLLS {
+                let roll = BlockShape::ALL[rolls.gen_range(0, BlockShape::ALL.len())];
+                rolled.push(roll);
+                if !history.contains(&roll) {
+                    break;
+                }
+            }
+            assert_eq!(shape, *rolled.last().unwrap());
+            if history.contains(&shape) {
+                assert_eq!(rolled.len(), HistoryGenerator::ROLLS, "{:?} repeated early", shape);
+            }
+            history.rotate_right(1);
+            history[0] = shape;
+        }
+    }
+}
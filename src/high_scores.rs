@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use serde::Serialize;
+use crate::mode::GameMode;
+use crate::mode::Ranking;
+use crate::util::read_config_file;
+use crate::util::write_config_file;
+
+const SCORES_FILE: &str = "scores.toml";
+const SCORES_VERSION: i64 = 1;
+/// How many entries each mode's table keeps.
+pub const TABLE_SIZE: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 10;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// Seconds the game lasted.
+    pub time: f32,
+    /// When the game was played, as YYYY-MM-DD.
+    pub date: String,
+    /// Seconds taken to reach every 10 lines, or every section in Master.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<f32>,
+    /// The grade reached, for Master.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grade: Option<String>,
+}
+
+/// The best games for every mode, kept on disk.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScores {
+    pub version: i64,
+    /// The name last entered, so it can be filled in next time.
+    pub last_name: String,
+    tables: BTreeMap<String, Vec<ScoreEntry>>,
+}
+
+impl Default for HighScores {
+    fn default() -> HighScores {
+        HighScores {
+            version: SCORES_VERSION,
+            last_name: String::new(),
+            tables: BTreeMap::new(),
+        }
+    }
+}
+
+impl HighScores {
+    /// Reads the scores file, starting a fresh table if there is none or it
+    /// can't be read.
+    pub fn load() -> (HighScores, Option<String>) {
+        match read_config_file(SCORES_FILE) {
+            Some(contents) => match toml::from_str::<HighScores>(&contents) {
+                Ok(high_scores) if high_scores.version <= SCORES_VERSION => (high_scores, None),
+                Ok(high_scores) => (
+                    HighScores::default(),
+                    Some(format!("{} was made by a newer version of the game ({})", SCORES_FILE, high_scores.version)),
+                ),
+                Err(error) => (
+                    HighScores::default(),
+                    Some(format!("Couldn't read {} ({})", SCORES_FILE, error.message().replace('\n', " "))),
+                ),
+            },
+            None => (HighScores::default(), None),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let contents = toml::to_string(self).map_err(|error| error.to_string())?;
+        write_config_file(SCORES_FILE, &contents)
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[ScoreEntry] {
+        self.tables.get(&mode.name()).map(|table| table.as_slice()).unwrap_or(&[])
+    }
+
+    /// The best entry for `mode`, to race against.
+    pub fn personal_best(&self, mode: GameMode) -> Option<&ScoreEntry> {
+        self.table(mode).first()
+    }
+
+    /// Where `entry` would go in the table, or None if it doesn't make it.
+    pub fn rank(&self, mode: GameMode, entry: &ScoreEntry) -> Option<usize> {
+        let table = self.table(mode);
+        let rank = table.iter()
+            .position(|other| match mode.ranking() {
+                Ranking::Score => entry.score > other.score,
+                Ranking::Time => entry.time < other.time,
+            })
+            .unwrap_or(table.len());
+        if rank < TABLE_SIZE {
+            Some(rank)
+        } else {
+            None
+        }
+    }
+
+    /// Adds `entry` to the table, returning where it went.
+    pub fn insert(&mut self, mode: GameMode, entry: ScoreEntry) -> Option<usize> {
+        let rank = self.rank(mode, &entry)?;
+        self.last_name = entry.name.clone();
+        let table = self.tables.entry(mode.name()).or_default();
+        table.insert(rank, entry);
+        table.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::Messiness;
+
+    fn entry(name: &str, score: u32, time: f32) -> ScoreEntry {
+        ScoreEntry {
+            name: name.to_string(),
+            score,
+            lines: 0,
+            level: 1,
+            time,
+            date: "2024-01-01".to_string(),
+            splits: Vec::new(),
+            grade: None,
+        }
+    }
+
+    fn names(high_scores: &HighScores, mode: GameMode) -> Vec<&str> {
+        high_scores.table(mode).iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn score_tables_put_the_highest_first() {
+        let mut high_scores = HighScores::default();
+        let mode = GameMode::Marathon;
+        assert_eq!(high_scores.insert(mode, entry("b", 500, 90.0)), Some(0));
+        assert_eq!(high_scores.insert(mode, entry("a", 900, 10.0)), Some(0));
+        assert_eq!(high_scores.insert(mode, entry("c", 100, 5.0)), Some(2));
+        assert_eq!(names(&high_scores, mode), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn time_tables_put_the_fastest_first() {
+        let mut high_scores = HighScores::default();
+        let mode = GameMode::Sprint(40);
+        assert_eq!(high_scores.insert(mode, entry("b", 0, 60.0)), Some(0));
+        assert_eq!(high_scores.insert(mode, entry("a", 0, 45.5)), Some(0));
+        assert_eq!(high_scores.insert(mode, entry("c", 9999, 80.0)), Some(2));
+        assert_eq!(names(&high_scores, mode), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn ties_go_after_the_entries_already_there() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(GameMode::Marathon, entry("first", 500, 0.0));
+        assert_eq!(high_scores.insert(GameMode::Marathon, entry("second", 500, 0.0)), Some(1));
+        high_scores.insert(GameMode::Sprint(40), entry("first", 0, 50.0));
+        assert_eq!(high_scores.insert(GameMode::Sprint(40), entry("second", 0, 50.0)), Some(1));
+    }
+
+    #[test]
+    fn full_tables_keep_the_best() {
+        let mut high_scores = HighScores::default();
+        let mode = GameMode::Marathon;
+        for score in 1..=TABLE_SIZE as u32 {
+            high_scores.insert(mode, entry(&score.to_string(), score * 100, 0.0));
+        }
+        // Ties with the last place don't make it.
+        assert_eq!(high_scores.rank(mode, &entry("low", 100, 0.0)), None);
+        assert_eq!(high_scores.insert(mode, entry("low", 50, 0.0)), None);
+        assert_eq!(high_scores.insert(mode, entry("high", 550, 0.0)), Some(5));
+        let table = high_scores.table(mode);
+        assert_eq!(table.len(), TABLE_SIZE);
+        assert_eq!(table.last().unwrap().score, 200);
+        assert!(!names(&high_scores, mode).contains(&"low"));
+    }
+
+    #[test]
+    fn modes_keep_their_own_tables() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(GameMode::Sprint(40), entry("a", 0, 50.0));
+        assert!(high_scores.table(GameMode::Sprint(20)).is_empty());
+        high_scores.insert(GameMode::Dig(10, Messiness::Messy), entry("a", 0, 50.0));
+        assert!(high_scores.table(GameMode::Dig(10, Messiness::Clean)).is_empty());
+        assert_eq!(high_scores.personal_best(GameMode::Sprint(40)).map(|entry| entry.time), Some(50.0));
+    }
+}
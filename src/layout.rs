@@ -0,0 +1,95 @@
+use macroquad::prelude::*;
+use crate::board::Board;
+use crate::SCREEN_HEIGHT;
+
+/// The biggest a cell is drawn, the size of the block texture.
+const MAX_CELL_SIZE: f32 = 16.0;
+/// Hidden rows left on screen above the board, so blocks can be seen coming
+/// in.
+const PEEK_ROWS: f32 = 2.0;
+/// Where the first upcoming block is shown, in rows down the side panel.
+const NEXT_ROW: f32 = 1.0;
+/// The upcoming blocks after the first are stacked below it, drawn smaller.
+const QUEUE_ROW: f32 = 5.5;
+const QUEUE_SPACING: f32 = 2.5;
+pub const QUEUE_SCALE: f32 = 0.5;
+/// The held block goes beside the queue, this many columns over.
+const HELD_COLUMN: f32 = 3.0;
+
+/// Where the board goes on screen, worked out from its size so any board
+/// fits: cells shrink to fit its height, and the side panel moves over to
+/// make room for its width.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Layout {
+    /// How big a cell is drawn, in pixels.
+    pub cell: f32,
+    /// The top left corner of the board's top left cell, which is hidden and
+    /// so usually off screen.
+    pub origin: Vec2,
+    /// The right edge of the right wall, where the side panel starts.
+    pub side_x: f32,
+}
+
+impl Layout {
+    pub fn new(board: &Board) -> Layout {
+        let cell = (SCREEN_HEIGHT as f32 / (board.visible_height() as f32 + PEEK_ROWS)).floor().min(MAX_CELL_SIZE);
+        // The left wall sits at the edge, and the visible rows at the bottom.
+        let origin = vec2(cell, SCREEN_HEIGHT as f32 - board.height() as f32 * cell);
+        Layout {
+            cell,
+            origin,
+            side_x: origin.x + (board.width() + 1) as f32 * cell,
+        }
+    }
+
+    /// Where a position in cells (which can be off the board) is on screen.
+    pub fn screen_position(&self, position: Vec2) -> Vec2 {
+        self.origin + position * self.cell
+    }
+
+    /// Where the side panel's text lines up, past the queue of upcoming
+    /// blocks.
+    pub fn text_x(&self) -> f32 {
+        self.side_x + self.cell * 4.0
+    }
+
+    /// Draws `texture` stretched over the cell at `position`.
+    pub fn draw_cell(&self, texture: Texture2D, position: Vec2, color: Color) {
+        self.draw_scaled_cell(texture, position, 1.0, color);
+    }
+
+    /// Draws `texture` over a cell `scale` times the usual size.
+    pub fn draw_scaled_cell(&self, texture: Texture2D, position: Vec2, scale: f32, color: Color) {
+        let screen = self.screen_position(position);
+        draw_texture_ex(
+            texture,
+            screen.x,
+            screen.y,
+            color,
+            DrawTextureParams {
+                dest_size: Some(vec2(self.cell * scale, self.cell * scale)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// A spot to the right of the board, past the wall, where upcoming and
+/// held blocks are shown, in cells. `y` counts from the top of the
+/// visible rows.
+fn side_position(board: &Board, x: f32, y: f32) -> Vec2 {
+    vec2(board.width() as f32 + 2.0 + x, board.hidden_rows() as f32 + y)
+}
+
+/// Where the queue shows the block `index` places from the front.
+pub fn queue_position(board: &Board, index: usize) -> Vec2 {
+    match index {
+        0 => side_position(board, 0.0, NEXT_ROW),
+        _ => side_position(board, 0.0, QUEUE_ROW + (index - 1) as f32 * QUEUE_SPACING),
+    }
+}
+
+/// Where the held block is shown.
+pub fn held_position(board: &Board) -> Vec2 {
+    side_position(board, HELD_COLUMN, NEXT_ROW)
+}
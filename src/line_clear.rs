@@ -0,0 +1,149 @@
+use macroquad::prelude::*;
+use crate::Game;
+use crate::COLORS;
+
+/// How long rows that are taken out straight away flash where they were, in
+/// ticks.
+const FLASH_TICKS: f32 = 20.0;
+/// How much of a line clear delay the rows spend flashing before they start
+/// to dissolve.
+const FLASH_PART: f32 = 0.3;
+/// How fast sparks fall, in cells per tick per tick.
+const SPARK_GRAVITY: f32 = 0.015;
+
+/// How big a clear looks and feels, for singles through tetrises.
+struct Intensity {
+    sparks_per_cell: u32,
+    /// How fast sparks fly out, in cells per tick.
+    speed: f32,
+    /// How bright the flash gets, from 0 to 1.
+    flash: f32,
+    /// How hard the screen shakes, 1 being a hard drop.
+    shake: f32,
+}
+
+const INTENSITIES: [Intensity; 4] = [
+    Intensity { sparks_per_cell: 1, speed: 0.15, flash: 0.5, shake: 0.5 },
+    Intensity { sparks_per_cell: 2, speed: 0.2, flash: 0.65, shake: 0.75 },
+    Intensity { sparks_per_cell: 3, speed: 0.25, flash: 0.8, shake: 1.0 },
+    Intensity { sparks_per_cell: 5, speed: 0.35, flash: 1.0, shake: 1.5 },
+];
+
+fn intensity(lines: usize) -> &'static Intensity {
+    &INTENSITIES[lines.clamp(1, INTENSITIES.len()) - 1]
+}
+
+/// A bit of a cleared row flying off. Like the background's particles, but
+/// thrown out and pulled back down, and gone after a while. Positions are in
+/// cells.
+#[derive(Clone)]
+pub struct Spark {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub radius: f32,
+    /// Ticks left before it's gone.
+    pub life: f32,
+    pub color: Color,
+}
+
+/// Rows that were taken out straight away, flashing where they were.
+#[derive(Clone)]
+pub struct ClearFlash {
+    pub rows: Vec<usize>,
+    /// Ticks left of the flash.
+    pub timer: f32,
+    pub strength: f32,
+}
+
+/// Starts the effects for the clear the engine just made: sparks out of every
+/// cell, a screen shake, and a flash if the rows are already gone.
+pub fn start_line_clear(game: &mut Game, lines: usize) {
+    let intensity = intensity(lines);
+    game.shake_by(intensity.shake);
+    if !game.engine.is_clearing() {
+        game.clear_flash = Some(ClearFlash {
+            rows: game.engine.cleared_rows.iter().map(|(y, _)| *y).collect(),
+            timer: FLASH_TICKS,
+            strength: intensity.flash,
+        });
+    }
+    for (y, row) in game.engine.cleared_rows.iter() {
+        for (x, &cell) in row.iter().enumerate() {
+            for _ in 0..intensity.sparks_per_cell {
+                let angle = game.effects_rng.gen_range(0.0, std::f32::consts::TAU);
+                let speed = intensity.speed * game.effects_rng.gen_range(0.5, 1.0);
+                game.sparks.push(Spark {
+                    position: vec2(x as f32 + 0.5, *y as f32 + 0.5),
+                    // Thrown up a little more than down, to arc back over.
+                    velocity: vec2(angle.cos(), angle.sin() - 0.5) * speed,
+                    radius: game.effects_rng.gen_range(1.0, 2.5),
+                    life: game.effects_rng.gen_range(20.0, 45.0),
+                    color: COLORS[cell as usize],
+                });
+            }
+        }
+    }
+}
+
+/// Moves the sparks and fades the flash by a tick.
+pub fn update_line_clears(game: &mut Game) {
+    for spark in game.sparks.iter_mut() {
+        spark.position += spark.velocity;
+        spark.velocity.y += SPARK_GRAVITY;
+        spark.life -= 1.0;
+    }
+    game.sparks.retain(|spark| spark.life > 0.0);
+    if let Some(flash) = game.clear_flash.as_mut() {
+        flash.timer -= 1.0;
+        if flash.timer <= 0.0 {
+            game.clear_flash = None;
+        }
+    }
+}
+
+/// The color a cell of a row being cleared is drawn in, and how big, as the
+/// line clear delay goes by. The row flashes white, then dissolves from the
+/// middle out.
+pub fn clearing_cell(game: &Game, x: usize, color: Color) -> (Color, f32) {
+    let engine = &game.engine;
+    let lines = engine.cleared_rows.len();
+    let progress = engine.line_clear_progress();
+    if progress < FLASH_PART {
+        let flash = intensity(lines).flash * (1.0 - progress / FLASH_PART);
+        return (Color::from_vec(color.to_vec().lerp(WHITE.to_vec(), flash)), 1.0);
+    }
+    let dissolve = (progress - FLASH_PART) / (1.0 - FLASH_PART);
+    let half_width = engine.board.width() as f32 / 2.0;
+    // 0 for the middle columns, 1 at the walls, so the middle goes first.
+    let from_middle = (x as f32 + 0.5 - half_width).abs() / half_width;
+    let scale = ((1.0 - dissolve) * 2.0 - (1.0 - from_middle)).clamp(0.0, 1.0);
+    (color, scale)
+}
+
+/// Draws the sparks and flash over the board.
+pub fn render_line_clears(game: &Game) {
+    let layout = game.layout();
+    if let Some(flash) = &game.clear_flash {
+        let alpha = flash.strength * flash.timer / FLASH_TICKS;
+        for &y in flash.rows.iter() {
+            let screen = layout.screen_position(vec2(0.0, y as f32));
+            draw_rectangle(
+                screen.x,
+                screen.y,
+                game.engine.board.width() as f32 * layout.cell,
+                layout.cell,
+                Color { a: alpha, ..WHITE },
+            );
+        }
+    }
+    let alpha = game.tick_alpha();
+    for spark in game.sparks.iter() {
+        let screen = layout.screen_position(spark.position + spark.velocity * alpha);
+        draw_circle(
+            screen.x.round(),
+            screen.y.round(),
+            spark.radius,
+            Color { a: (spark.life / 20.0).min(1.0), ..spark.color },
+        );
+    }
+}
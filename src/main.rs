@@ -1,25 +1,77 @@
-use macroquad::rand::gen_range;
-use macroquad::audio::stop_sound;
+use macroquad::rand::RandGenerator;
 use macroquad::audio::Sound;
 use crate::background::*;
 use crate::game_state::*;
 use crate::menu_state::*;
-use crate::block::Block;
-use macroquad::audio::play_sound;
+use crate::controls_state::*;
+use crate::pause_state::*;
+use crate::mode_state::*;
+use crate::settings_state::*;
+use crate::scores_state::*;
+use crate::replay_state::*;
+use crate::summary_state::*;
+use crate::high_scores::HighScores;
+use crate::engine::Buttons;
+use crate::engine::Engine;
+use crate::controls::Action;
+use crate::gamepad::Gamepads;
+use crate::layout::Layout;
+use crate::line_clear::ClearFlash;
+use crate::line_clear::Spark;
+use crate::menu::Menu;
+use crate::mode::GameMode;
+use crate::replay::Playback;
+use crate::replay::Replay;
+use crate::settings::Settings;
+use crate::sprites::Sprites;
 use crate::util::load_sound_file;
-use macroquad::audio::PlaySoundParams;
+use crate::util::random_seed;
+use crate::util::seed_from_args;
+use crate::util::seeded_rng;
 use crate::util::load_texture_file;
+use macroquad::audio::play_sound;
+use macroquad::audio::PlaySoundParams;
+use macroquad::audio::set_sound_volume;
 use macroquad::prelude::*;
 
 mod background;
 mod block;
+mod board;
+mod controls;
+mod controls_state;
+mod engine;
+mod gamepad;
+mod generator;
+mod menu;
 mod menu_state;
+mod mode;
+mod mode_state;
+mod pause_state;
+mod replay;
+mod replay_state;
+mod rotation;
+mod scores_state;
+mod scoring;
+mod settings;
+mod settings_state;
+mod sprites;
+mod summary_state;
 mod game_state;
+mod high_scores;
+mod layout;
+mod line_clear;
 mod util;
 
 pub const SCREEN_WIDTH: i32 = 320;
 pub const SCREEN_HEIGHT: i32 = 256;
-pub const COLORS: [Color; 10] = [
+const EFFECTS_STREAM: u64 = 0x9e3779b97f4a7c15;
+/// The game runs in fixed steps of a 60th of a second, however fast the
+/// screen refreshes, so it plays out the same everywhere.
+pub const TICK_SECONDS: f32 = 1.0 / 60.0;
+/// The most time caught up on in one frame, so a long hitch doesn't turn into
+/// a burst of ticks.
+const MAX_CATCH_UP: f32 = 0.25;
+pub const COLORS: [Color; 11] = [
     Color {
         r: 0.156,
         g: 0.172,
@@ -80,22 +132,63 @@ pub const COLORS: [Color; 10] = [
         b: 0.152,
         a: 1.0,
     },
+    // Garbage
+    Color {
+        r: 0.415,
+        g: 0.443,
+        b: 0.517,
+        a: 1.0,
+    },
 ];
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum GameState {
     Menu,
+    ModeSelect,
+    Settings,
+    Controls,
+    Scores,
     Game,
+    Paused,
+    /// Stats for a finished Zen session.
+    Summary,
+    Replays,
+    Playback,
 }
 
 pub struct Game {
     pub time: f32,
     pub state: GameState,
-    pub placed_blocks: [[u8; 12]; 16],
-    pub block: Block,
-    pub game_over: bool,
-    pub next_block: Block,
-    pub held_block: Option<Block>,
+    pub mode: GameMode,
+    /// The variant of each mode picked on the mode select screen.
+    pub mode_choices: Vec<GameMode>,
+    pub engine: Engine,
+    /// Where the engine's blocks are drawn.
+    pub sprites: Sprites,
+    /// Seed every new game starts from, if the player asked for one.
+    pub seed: Option<u64>,
+    /// Random stream for screen shake and particles, kept apart from the
+    /// piece stream so cosmetics can never change which pieces are dealt.
+    pub effects_rng: RandGenerator,
+    pub settings: Settings,
+    pub gamepads: Gamepads,
+    /// Shown on the menu when settings or scores couldn't be loaded or saved.
+    pub storage_error: Option<String>,
+    pub high_scores: HighScores,
+    /// The name being typed in after a high score, if there is one.
+    pub name_entry: Option<String>,
+    /// Where the last game landed in the high score table.
+    pub new_record: Option<usize>,
+    /// Time that still has to be ticked through, in seconds.
+    pub tick_time: f32,
+    /// Buttons pressed since the last tick, so a press lands on the next tick
+    /// even if a frame goes by without one.
+    pub pressed_buttons: Buttons,
+    /// The game being played, as it's played.
+    pub recording: Replay,
+    /// The saved replays, read when the replay list is opened.
+    pub replays: Vec<Replay>,
+    pub playback: Option<Playback>,
 
     pub block_texture: Option<Texture2D>,
     pub background_texture: Option<Texture2D>,
@@ -106,25 +199,56 @@ pub struct Game {
     pub hit_sfx: Option<Sound>,
 
     pub particles: Vec<Particle>,
+    /// Bits of cleared rows flying off the board.
+    pub sparks: Vec<Spark>,
+    pub clear_flash: Option<ClearFlash>,
 
     pub play: bool,
     pub menu_delay: f32,
-    pub played_game_over: bool,
-    pub has_switched: bool,
+    pub main_menu: Menu,
+    pub mode_menu: Menu,
+    pub settings_menu: Menu,
+    pub controls_menu: Menu,
+    pub controls_listening: bool,
+    pub pause_menu: Menu,
+    pub replays_menu: Menu,
+    /// Where leaving the settings or controls screen goes, since they can be
+    /// opened from the pause menu as well as the main menu.
+    pub screen_return: GameState,
+    /// Set by the Quit entry, to end the main loop.
+    pub quit: bool,
 
     pub screen_shake: Vec2,
 }
 
 impl Game {
     async fn new() -> Game {
+        let seed = seed_from_args();
+        let engine_seed = seed.unwrap_or_else(random_seed);
+        let (settings, settings_error) = Settings::load();
+        let (high_scores, scores_error) = HighScores::load();
+        let recording = Replay::new(GameMode::Marathon, engine_seed, settings.handling);
+        let engine = Engine::new(GameMode::Marathon.rules(), settings.handling, engine_seed);
         Game {
             time: 0.0,
             state: GameState::Game,
-            placed_blocks: [[8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8]; 16],
-            block: Block::default(),
-            game_over: false,
-            next_block: Block::default(),
-            held_block: None,
+            mode: GameMode::Marathon,
+            mode_choices: GameMode::ALL.to_vec(),
+            sprites: Sprites::new(&engine),
+            engine,
+            seed,
+            effects_rng: seeded_rng(engine_seed.wrapping_add(EFFECTS_STREAM)),
+            settings,
+            gamepads: Gamepads::new(),
+            storage_error: settings_error.or(scores_error),
+            high_scores,
+            name_entry: None,
+            new_record: None,
+            tick_time: 0.0,
+            pressed_buttons: Buttons::default(),
+            recording,
+            replays: Vec::new(),
+            playback: None,
 
             block_texture: Some(load_texture_file("res/img/block.png".to_string()).await),
             background_texture: Some(load_texture_file("res/img/background.png".to_string()).await),
@@ -135,43 +259,125 @@ impl Game {
             hit_sfx: Some(load_sound_file("res/sfx/hit.ogg".to_string()).await),
 
             particles: Vec::new(),
+            sparks: Vec::new(),
+            clear_flash: None,
 
             play: false,
             menu_delay: 30.0,
-            played_game_over: false,
-            has_switched: false,
+            main_menu: Menu::default(),
+            mode_menu: Menu::default(),
+            settings_menu: Menu::default(),
+            controls_menu: Menu::default(),
+            controls_listening: false,
+            pause_menu: Menu::default(),
+            replays_menu: Menu::default(),
+            screen_return: GameState::Menu,
+            quit: false,
 
             screen_shake: vec2(0.0, 0.0),
         }
     }
 
-    fn block_collides(&self) -> bool {
-        let shape = self.block.get_shape();
-        for y in 0..shape.len() {
-            for x in 0..shape[y].len() {
-                if shape[y][x] != 0 {
-                    if self.block.position.y as usize + y >= self.placed_blocks.len()
-                    || self.block.position.x as usize + x >= self.placed_blocks[0].len()
-                    || self.block.position.x + (x as f32) <= 0.0
-                    || self.placed_blocks[y + self.block.position.y as usize][x + self.block.position.x as usize] != 0 {
-                        return true;
-                    }
-                }
-            }
+    /// Throws away the current game and starts a fresh one, from the fixed
+    /// seed if there is one.
+    pub fn new_game(&mut self) {
+        let seed = self.seed.unwrap_or_else(random_seed);
+        self.engine = Engine::new(self.mode.rules(), self.settings.handling, seed);
+        self.sprites = Sprites::new(&self.engine);
+        self.recording = Replay::new(self.mode, seed, self.settings.handling);
+        self.pressed_buttons = Buttons::default();
+        self.sparks.clear();
+        self.clear_flash = None;
+        self.effects_rng = seeded_rng(seed.wrapping_add(EFFECTS_STREAM));
+        self.name_entry = None;
+        self.new_record = None;
+    }
+
+    /// Whether `action` was pressed this frame, on the keyboard or a gamepad.
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.settings.bindings.is_pressed(action) || self.gamepads.is_pressed(action)
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.settings.bindings.is_down(action) || self.gamepads.is_down(action)
+    }
+
+    pub fn save_high_scores(&mut self) {
+        if let Err(error) = self.high_scores.save() {
+            self.storage_error = Some(format!("Couldn't save scores: {}", error));
+        }
+    }
+
+    pub fn save_replay(&mut self) {
+        if let Err(error) = self.recording.save(&self.engine) {
+            self.storage_error = Some(format!("Couldn't save replay: {}", error));
+        }
+    }
+
+    pub fn save_settings(&mut self) {
+        if let Err(error) = self.settings.save() {
+            self.storage_error = Some(format!("Couldn't save settings: {}", error));
         }
-        false
+    }
+
+    /// Plays a sound effect at `volume`, scaled by the player's sfx volume.
+    pub fn play_effect(&self, sound: Option<Sound>, volume: f32) {
+        play_sound(
+            sound.unwrap(),
+            PlaySoundParams {
+                looped: false,
+                volume: volume * self.settings.audio.sfx_volume,
+            },
+        );
+    }
+
+    pub fn play_music(&self) {
+        play_sound(
+            self.music.unwrap(),
+            PlaySoundParams {
+                looped: true,
+                volume: self.music_volume(),
+            },
+        );
+    }
+
+    /// Silences the music without losing its place, or brings it back.
+    pub fn mute_music(&self, mute: bool) {
+        set_sound_volume(self.music.unwrap(), if mute { 0.0 } else { self.music_volume() });
+    }
+
+    fn music_volume(&self) -> f32 {
+        0.4 * self.settings.audio.music_volume
     }
 
     pub fn shake(&mut self) {
-        self.screen_shake = vec2(gen_range(-10.0, 10.0), gen_range(-10.0, 10.0));
+        self.shake_by(1.0);
+    }
+
+    /// Shakes the screen `strength` times as hard as usual.
+    pub fn shake_by(&mut self, strength: f32) {
+        let distance = 10.0 * strength;
+        self.screen_shake = vec2(self.effects_rng.gen_range(-distance, distance), self.effects_rng.gen_range(-distance, distance));
+    }
+
+    /// How far between the last tick and the next the current frame is, from
+    /// 0 to 1.
+    pub fn tick_alpha(&self) -> f32 {
+        self.tick_time / TICK_SECONDS
+    }
+
+    /// Where the current board is drawn.
+    pub fn layout(&self) -> Layout {
+        Layout::new(&self.engine.board)
     }
 }
 
 fn window_conf() -> Conf {
+    let (settings, _) = Settings::load();
     Conf {
         window_title: "RS-tris".to_string(),
-        window_width: SCREEN_WIDTH * 3,
-        window_height: SCREEN_HEIGHT * 3,
+        window_width: SCREEN_WIDTH * settings.visuals.window_scale,
+        window_height: SCREEN_HEIGHT * settings.visuals.window_scale,
         ..Default::default()
     }
 }
@@ -188,12 +394,11 @@ async fn main() {
     let mut game = Game::new().await;
     for _ in 0..30 {
         game.particles.push(Particle {
-            position: vec2(camera.target.x + gen_range(-SCREEN_WIDTH as f32 * 0.5, SCREEN_WIDTH as f32 * 0.5) - 16.0, camera.target.y + gen_range(-SCREEN_HEIGHT as f32 * 0.5, SCREEN_HEIGHT as f32 * 0.5)),
-            radius: gen_range(20.0, 40.0),
+            position: vec2(camera.target.x + game.effects_rng.gen_range(-SCREEN_WIDTH as f32 * 0.5, SCREEN_WIDTH as f32 * 0.5) - 16.0, camera.target.y + game.effects_rng.gen_range(-SCREEN_HEIGHT as f32 * 0.5, SCREEN_HEIGHT as f32 * 0.5)),
+            radius: game.effects_rng.gen_range(20.0, 40.0),
         });
     }
     game.state = GameState::Menu;
-    game.block.position = vec2(5.0, 0.0);
     let mut is_other_frame = true;
     loop {
         game.time += get_frame_time();
@@ -201,24 +406,35 @@ async fn main() {
             game.time = 0.0;
         }
         is_other_frame = !is_other_frame;
+        game.gamepads.update();
         update_background(&mut game);
-        if game.state == GameState::Game {
-            if !update_game(&mut game) {
-                game.game_over = true;
-                if !game.played_game_over {
-                    game.played_game_over = true;
-                    stop_sound(game.music.unwrap());
-                    play_sound(
-                        game.game_over_sfx.unwrap(),
-                        PlaySoundParams {
-                            looped: false,
-                            volume: 0.8,
-                        },
-                    );
+        match game.state {
+            GameState::Menu => update_menu(&mut game),
+            GameState::ModeSelect => update_mode_select(&mut game),
+            GameState::Settings => update_settings(&mut game),
+            GameState::Controls => update_controls(&mut game),
+            GameState::Scores => update_scores(&mut game),
+            GameState::Game => update_game(&mut game),
+            GameState::Paused => update_pause(&mut game),
+            GameState::Summary => update_summary(&mut game),
+            GameState::Replays => update_replays(&mut game),
+            GameState::Playback => update_playback(&mut game),
+        }
+        if game.quit {
+            break;
+        }
+        if let GameState::Game | GameState::Playback = game.state {
+            game.tick_time = (game.tick_time + get_frame_time()).min(MAX_CATCH_UP);
+            while game.tick_time >= TICK_SECONDS {
+                game.tick_time -= TICK_SECONDS;
+                match game.state {
+                    GameState::Game => tick_game(&mut game),
+                    GameState::Playback => tick_playback(&mut game),
+                    _ => {},
                 }
             }
         } else {
-            update_menu(&mut game);
+            game.tick_time = 0.0;
         }
         if is_other_frame {
             game.screen_shake *= -0.9;
@@ -231,10 +447,17 @@ async fn main() {
         clear_background(BLACK);
 
         render_background(&game);
-        if game.state == GameState::Game {
-            render_game(&game);
-        } else {
-            render_menu(&game);
+        match game.state {
+            GameState::Menu => render_menu(&game),
+            GameState::ModeSelect => render_mode_select(&game),
+            GameState::Settings => render_settings(&game),
+            GameState::Controls => render_controls(&game),
+            GameState::Scores => render_scores(&game),
+            GameState::Game => render_game(&game),
+            GameState::Paused => render_pause(&game),
+            GameState::Summary => render_summary(&game),
+            GameState::Replays => render_replays(&game),
+            GameState::Playback => render_playback(&game),
         }
 
         set_default_camera();
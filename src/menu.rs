@@ -0,0 +1,113 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::Game;
+use crate::COLORS;
+
+pub struct MenuEntry {
+    pub label: String,
+    /// Shown to the right of the label, for entries that change a setting.
+    pub value: Option<String>,
+}
+
+impl MenuEntry {
+    pub fn new(label: &str) -> MenuEntry {
+        MenuEntry {
+            label: label.to_string(),
+            value: None,
+        }
+    }
+
+    pub fn with_value(label: &str, value: String) -> MenuEntry {
+        MenuEntry {
+            value: Some(value),
+            ..MenuEntry::new(label)
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuEvent {
+    /// The selection moved to another entry.
+    Moved,
+    Select(usize),
+    /// Left (-1) or right (1) was pressed on an entry.
+    Adjust(usize, i32),
+    Back,
+}
+
+/// A vertical list of entries, moved through with the menu actions. Every
+/// screen uses one so they all navigate and sound the same.
+#[derive(Copy, Clone, Default)]
+pub struct Menu {
+    pub selection: usize,
+}
+
+impl Menu {
+    /// Handles this frame's input for `entries`, keeping the selection on one
+    /// of them.
+    pub fn update(&mut self, game: &Game, entries: &[MenuEntry]) -> Option<MenuEvent> {
+        if entries.is_empty() {
+            return if game.is_pressed(Action::Back) { Some(MenuEvent::Back) } else { None };
+        }
+        self.selection = self.selection.min(entries.len() - 1);
+        let start = self.selection;
+
+        if game.is_pressed(Action::MenuUp) {
+            self.selection = (self.selection + entries.len() - 1) % entries.len();
+        }
+        if game.is_pressed(Action::MenuDown) {
+            self.selection = (self.selection + 1) % entries.len();
+        }
+        if self.selection != start {
+            Some(MenuEvent::Moved)
+        } else if game.is_pressed(Action::Confirm) {
+            Some(MenuEvent::Select(self.selection))
+        } else if game.is_pressed(Action::MenuLeft) {
+            Some(MenuEvent::Adjust(self.selection, -1))
+        } else if game.is_pressed(Action::MenuRight) {
+            Some(MenuEvent::Adjust(self.selection, 1))
+        } else if game.is_pressed(Action::Back) {
+            Some(MenuEvent::Back)
+        } else {
+            None
+        }
+    }
+
+    /// Draws the entries downwards from `(x, y)`, with values lined up at
+    /// `value_x` past the labels.
+    pub fn render(&self, entries: &[MenuEntry], x: f32, y: f32, spacing: f32, value_x: f32) {
+        for (i, entry) in entries.iter().enumerate() {
+            let color = if i == self.selection {
+                COLORS[1]
+            } else {
+                WHITE
+            };
+            let row_y = y + i as f32 * spacing;
+            draw_text(&entry.label, x, row_y, 16.0, color);
+            if let Some(value) = &entry.value {
+                draw_text(value, x + value_x, row_y, 16.0, color);
+            }
+        }
+    }
+}
+
+/// Runs the menu picked out by `menu` for a frame, with sound feedback.
+pub fn update_menu_widget(game: &mut Game, menu: fn(&mut Game) -> &mut Menu, entries: &[MenuEntry]) -> Option<MenuEvent> {
+    let mut widget = *menu(game);
+    let event = widget.update(game, entries);
+    *menu(game) = widget;
+    if let Some(event) = event {
+        play_menu_sound(game, event);
+    }
+    event
+}
+
+/// The sound every menu makes for `event`.
+pub fn play_menu_sound(game: &Game, event: MenuEvent) {
+    match event {
+        MenuEvent::Moved
+        | MenuEvent::Adjust(..) => game.play_effect(game.hit_sfx, 0.3),
+        MenuEvent::Select(_) => game.play_effect(game.hit_sfx, 0.6),
+        MenuEvent::Back => game.play_effect(game.hit_sfx, 0.45),
+    }
+}
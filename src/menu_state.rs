@@ -1,39 +1,66 @@
-use macroquad::audio::PlaySoundParams;
-use macroquad::audio::play_sound;
-use crate::util::delta_time;
+use crate::util::wrap_text;
 use macroquad::prelude::*;
+use crate::menu::update_menu_widget;
+use crate::menu::MenuEntry;
+use crate::menu::MenuEvent;
+use crate::mode::GameMode;
+use crate::replay::Replay;
 use crate::Game;
 use crate::GameState;
+use crate::COLORS;
 
-pub fn update_menu(game: &mut Game) {
-    if game.play {
-        game.menu_delay -= delta_time();
+fn menu_entries() -> Vec<MenuEntry> {
+    let mut entries = vec![
+        MenuEntry::new("Play"),
+        MenuEntry::new("Settings"),
+        MenuEntry::new("Controls"),
+        MenuEntry::new("High scores"),
+        MenuEntry::new("Replays"),
+    ];
+    // A browser tab can't close itself.
+    if cfg!(not(target_arch = "wasm32")) {
+        entries.push(MenuEntry::new("Quit"));
     }
-    if is_key_pressed(KeyCode::X)
-    && !game.play {
-        game.play = true;
-        game.shake();
-        play_sound(
-            game.play_sfx.unwrap(),
-            PlaySoundParams {
-                looped: false,
-                volume: 0.9
+    entries
+}
+
+pub fn update_menu(game: &mut Game) {
+    let entries = menu_entries();
+    if let Some(MenuEvent::Select(i)) = update_menu_widget(game, |game| &mut game.main_menu, &entries) {
+        match entries[i].label.as_str() {
+            "Play" => game.state = GameState::ModeSelect,
+            "Settings" => {
+                game.screen_return = GameState::Menu;
+                game.state = GameState::Settings;
             },
-        );
-    }
-    if game.menu_delay <= 0.0 {
-        game.state = GameState::Game;
-        play_sound(
-            game.music.unwrap(),
-            PlaySoundParams {
-                looped: true,
-                volume: 0.4,
+            "Controls" => {
+                game.controls_menu.selection = 0;
+                game.screen_return = GameState::Menu;
+                game.state = GameState::Controls;
             },
-        );
+            "Replays" => {
+                game.replays = Replay::load_all();
+                game.replays_menu.selection = 0;
+                game.state = GameState::Replays;
+            },
+            "High scores" => {
+                if !game.mode.has_high_scores() {
+                    game.mode = GameMode::Marathon;
+                }
+                game.state = GameState::Scores;
+            },
+            _ => game.quit = true,
+        }
     }
 }
 
 pub fn render_menu(game: &Game) {
-    draw_text("RS-tris", 95.0, 60.0 + (f32::sin(game.time as f32 * 2.0) * 10.0).round(), 32.0, WHITE);
-    draw_text("X to play", 110.0, 76.0 + (f32::sin(game.time as f32 * 2.0) * 10.0).round(), 16.0, WHITE);
-}
\ No newline at end of file
+    let bob = (f32::sin(game.time * 2.0) * 10.0).round();
+    draw_text("RS-tris", 95.0, 60.0 + bob, 32.0, WHITE);
+    game.main_menu.render(&menu_entries(), 110.0, 76.0 + bob, 16.0, 0.0);
+    if let Some(error) = &game.storage_error {
+        for (i, line) in wrap_text(error, 50).iter().enumerate() {
+            draw_text(line, -8.0, 220.0 + i as f32 * 10.0, 12.0, COLORS[1]);
+        }
+    }
+}
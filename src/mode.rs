@@ -0,0 +1,377 @@
+use serde::Deserialize;
+use serde::Serialize;
+use crate::board::STANDARD_HEIGHT;
+use crate::board::STANDARD_WIDTH;
+use crate::generator::GeneratorKind;
+use crate::rotation::RotationSystem;
+use crate::scoring::ScoringSystem;
+use crate::util::format_time_short;
+
+/// Line counts a sprint can be set to.
+pub const SPRINT_LINES: [u32; 3] = [20, 40, 100];
+/// The highest level a classic game can start on.
+pub const CLASSIC_MAX_START_LEVEL: u32 = 19;
+/// Garbage row counts a dig race can be set to.
+pub const DIG_ROWS: [u32; 3] = [10, 40, 100];
+/// Time limits, in seconds, an ultra can be set to.
+pub const ULTRA_SECONDS: [u32; 3] = [60, 120, 180];
+/// The most upcoming blocks a mode can show.
+pub const MAX_PREVIEWS: usize = 6;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum GameMode {
+    Marathon,
+    /// Clear this many lines as fast as possible.
+    Sprint(u32),
+    /// Score as much as possible in this many seconds.
+    Ultra(u32),
+    /// NES rules, starting from this level.
+    Classic(u32),
+    /// TGM rules, up to 20G and level 999.
+    Master,
+    /// Dig through this many rows of garbage as fast as possible.
+    Dig(u32, Messiness),
+    /// No speed up, no timer and no game over.
+    Zen,
+}
+
+/// How far the holes in a dig race's garbage wander from row to row.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Messiness {
+    Clean,
+    Messy,
+    Chaotic,
+}
+
+impl Messiness {
+    pub const ALL: [Messiness; 3] = [Messiness::Clean, Messiness::Messy, Messiness::Chaotic];
+
+    /// The chance of each hole moving away from the one below it.
+    pub fn chance(&self) -> f32 {
+        match self {
+            Messiness::Clean => 0.3,
+            Messiness::Messy => 0.7,
+            Messiness::Chaotic => 1.0,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Messiness::Clean => "clean",
+            Messiness::Messy => "messy",
+            Messiness::Chaotic => "chaotic",
+        }
+    }
+}
+
+/// What ends a game, other than topping out.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Goal {
+    Endless,
+    Lines(u32),
+    /// Seconds until the game ends.
+    Time(u32),
+    Level(u32),
+    /// Clear this many rows of garbage, which keeps coming up from the bottom.
+    Garbage(u32),
+}
+
+/// Which way a mode's high score table is sorted.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Ranking {
+    /// Highest score first. Every game counts, however it ended.
+    Score,
+    /// Fastest time first. Only games that reached the goal count.
+    Time,
+}
+
+/// What gives a block on the ground more time before it locks.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockReset {
+    /// Any move or rotation restarts the timer, up to 15 times per piece.
+    Move,
+    /// Only falling to a new lowest row restarts the timer.
+    Step,
+    /// The timer never restarts.
+    None,
+    /// There's no lock delay: the block locks as soon as gravity tries to pull
+    /// it through the ground, like on the NES.
+    Gravity,
+}
+
+impl LockReset {
+    /// The next rule a player can pick. Gravity locking is only for modes
+    /// that play like the NES.
+    pub fn next(&self) -> LockReset {
+        match self {
+            LockReset::Move => LockReset::Step,
+            LockReset::Step => LockReset::None,
+            LockReset::None
+            | LockReset::Gravity => LockReset::Move,
+        }
+    }
+}
+
+/// What happens when the stack reaches the top of the board.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TopOut {
+    GameOver,
+    /// The bottom half of the board is thrown away and play carries on.
+    Compress,
+}
+
+/// How fast blocks fall on each level, and for Master how short the delays
+/// around locking get.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SpeedCurve {
+    Guideline,
+    Nes,
+    /// TGM style: gravity climbs to 20G by level 500, then ARE, line clear
+    /// delay and lock delay shrink every section.
+    Master,
+}
+
+/// The knobs a game mode turns on the engine.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rules {
+    /// The size of the board in cells.
+    pub board_width: usize,
+    pub board_height: usize,
+    pub generator: GeneratorKind,
+    pub rotation_system: RotationSystem,
+    pub speed: SpeedCurve,
+    pub scoring: ScoringSystem,
+    pub start_level: u32,
+    /// `u32::MAX` keeps the game on level 1.
+    pub lines_per_level: u32,
+    /// Frames a block can sit on the ground before it locks.
+    pub lock_delay: f32,
+    pub lock_reset: LockReset,
+    /// Whether the player's handling settings pick the lock delay and reset
+    /// rule, in place of the two above.
+    pub player_lock: bool,
+    /// Frames after a block locks before the next one appears.
+    pub are: f32,
+    /// Extra frames before the next block when the last one cleared lines,
+    /// which the cleared rows spend flashing and dissolving on the board. At 0
+    /// they're taken out straight away.
+    pub line_clear_delay: f32,
+    /// Whether the player's handling settings can pick the line clear delay
+    /// in place of the one above. Modes against the clock keep theirs, so
+    /// everyone's times are made with the same delay.
+    pub player_line_clear: bool,
+    pub goal: Goal,
+    pub top_out: TopOut,
+    pub hold: bool,
+    pub hard_drop: bool,
+    /// How many upcoming blocks are shown, up to `MAX_PREVIEWS`.
+    pub previews: usize,
+    /// For garbage, how likely each row's hole is to move away from the one
+    /// below it, from 0 (one straight well) to 1 (a new hole every row).
+    pub garbage_messiness: f32,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 7] = [
+        GameMode::Marathon,
+        GameMode::Sprint(40),
+        GameMode::Ultra(120),
+        GameMode::Classic(0),
+        GameMode::Master,
+        GameMode::Dig(10, Messiness::Messy),
+        GameMode::Zen,
+    ];
+
+    /// The name used in save files. Each variant of a mode gets its own table.
+    pub fn name(&self) -> String {
+        match self {
+            GameMode::Marathon => "marathon".to_string(),
+            GameMode::Sprint(lines) => format!("sprint_{}", lines),
+            GameMode::Ultra(seconds) => format!("ultra_{}", seconds),
+            // One table for every start level, like the NES.
+            GameMode::Classic(_) => "classic".to_string(),
+            GameMode::Master => "master".to_string(),
+            // Messy garbage was the only kind before there was a choice, so
+            // it keeps the old tables.
+            GameMode::Dig(rows, Messiness::Messy) => format!("dig_{}", rows),
+            GameMode::Dig(rows, messiness) => format!("dig_{}_{}", rows, messiness.label()),
+            GameMode::Zen => "zen".to_string(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            GameMode::Marathon => "Marathon".to_string(),
+            GameMode::Sprint(lines) => format!("Sprint {}L", lines),
+            GameMode::Ultra(seconds) => format!("Ultra {}", format_time_short(*seconds)),
+            GameMode::Classic(level) => format!("Classic (level {})", level),
+            GameMode::Master => "Master".to_string(),
+            GameMode::Dig(rows, messiness) => format!("Dig {} ({})", rows, messiness.label()),
+            GameMode::Zen => "Zen".to_string(),
+        }
+    }
+
+    /// The label for this mode's high score table.
+    pub fn table_label(&self) -> String {
+        match self {
+            GameMode::Classic(_) => "Classic".to_string(),
+            _ => self.label(),
+        }
+    }
+
+    /// Every setting of this mode, like each sprint length.
+    pub fn variants(&self) -> Vec<GameMode> {
+        match self {
+            GameMode::Marathon => vec![GameMode::Marathon],
+            GameMode::Master => vec![GameMode::Master],
+            GameMode::Zen => vec![GameMode::Zen],
+            GameMode::Sprint(_) => SPRINT_LINES.iter().map(|&lines| GameMode::Sprint(lines)).collect(),
+            GameMode::Ultra(_) => ULTRA_SECONDS.iter().map(|&seconds| GameMode::Ultra(seconds)).collect(),
+            GameMode::Classic(_) => (0..=CLASSIC_MAX_START_LEVEL).map(GameMode::Classic).collect(),
+            GameMode::Dig(..) => DIG_ROWS.iter()
+                .flat_map(|&rows| Messiness::ALL.map(|messiness| GameMode::Dig(rows, messiness)))
+                .collect(),
+        }
+    }
+
+    /// The next (1) or previous (-1) variant of this mode, wrapping around.
+    pub fn cycle(&self, direction: i32) -> GameMode {
+        let variants = self.variants();
+        let index = variants.iter().position(|mode| mode == self).unwrap_or(0) as i32;
+        variants[(index + direction).rem_euclid(variants.len() as i32) as usize]
+    }
+
+    /// Zen never ends, so it has nothing to put in a table.
+    pub fn has_high_scores(&self) -> bool {
+        *self != GameMode::Zen
+    }
+
+    pub fn ranking(&self) -> Ranking {
+        match self {
+            GameMode::Marathon
+            | GameMode::Ultra(_)
+            | GameMode::Classic(_)
+            | GameMode::Master
+            | GameMode::Zen => Ranking::Score,
+            GameMode::Sprint(_)
+            | GameMode::Dig(..) => Ranking::Time,
+        }
+    }
+
+    /// One line about the mode for the mode select screen.
+    pub fn description(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Clear lines as the speed climbs",
+            GameMode::Sprint(_) => "Clear the lines as fast as you can",
+            GameMode::Ultra(_) => "Score all you can before time runs out",
+            GameMode::Classic(_) => "NES rules: no hold, no hard drop",
+            GameMode::Master => "Survive to level 999 at 20G",
+            GameMode::Dig(..) => "Dig through the garbage as fast as you can",
+            GameMode::Zen => "Relax: no timer and no game over",
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        match self {
+            GameMode::Marathon => Rules {
+                board_width: STANDARD_WIDTH,
+                board_height: STANDARD_HEIGHT,
+                generator: GeneratorKind::SevenBag,
+                rotation_system: RotationSystem::Srs,
+                speed: SpeedCurve::Guideline,
+                scoring: ScoringSystem::Guideline,
+                start_level: 1,
+                lines_per_level: 10,
+                lock_delay: 30.0,
+                lock_reset: LockReset::Move,
+                player_lock: true,
+                are: 0.0,
+                line_clear_delay: 0.0,
+                player_line_clear: true,
+                goal: Goal::Endless,
+                top_out: TopOut::GameOver,
+                hold: true,
+                hard_drop: true,
+                previews: 5,
+                garbage_messiness: 0.0,
+            },
+            GameMode::Sprint(lines) => Rules {
+                lines_per_level: u32::MAX,
+                player_line_clear: false,
+                goal: Goal::Lines(*lines),
+                ..GameMode::Marathon.rules()
+            },
+            GameMode::Ultra(seconds) => Rules {
+                player_line_clear: false,
+                goal: Goal::Time(*seconds),
+                ..GameMode::Marathon.rules()
+            },
+            GameMode::Classic(level) => Rules {
+                board_width: STANDARD_WIDTH,
+                board_height: STANDARD_HEIGHT,
+                generator: GeneratorKind::Random,
+                rotation_system: RotationSystem::Classic,
+                speed: SpeedCurve::Nes,
+                scoring: ScoringSystem::Nes,
+                start_level: *level,
+                lines_per_level: 10,
+                lock_delay: 0.0,
+                lock_reset: LockReset::Gravity,
+                player_lock: false,
+                are: 0.0,
+                // The NES takes rows out while the next piece is already
+                // falling, so there's no delay to pick.
+                line_clear_delay: 0.0,
+                player_line_clear: false,
+                goal: Goal::Endless,
+                top_out: TopOut::GameOver,
+                hold: false,
+                hard_drop: false,
+                previews: 1,
+                garbage_messiness: 0.0,
+            },
+            GameMode::Master => Rules {
+                board_width: STANDARD_WIDTH,
+                board_height: STANDARD_HEIGHT,
+                generator: GeneratorKind::History,
+                rotation_system: RotationSystem::Ars,
+                speed: SpeedCurve::Master,
+                scoring: ScoringSystem::Tgm,
+                start_level: 0,
+                lines_per_level: 100,
+                lock_delay: 30.0,
+                lock_reset: LockReset::Step,
+                player_lock: false,
+                are: 25.0,
+                // Replaced by the delay table as the level goes up.
+                line_clear_delay: 40.0,
+                player_line_clear: false,
+                goal: Goal::Level(999),
+                top_out: TopOut::GameOver,
+                hold: false,
+                hard_drop: true,
+                previews: 1,
+                garbage_messiness: 0.0,
+            },
+            GameMode::Dig(rows, messiness) => Rules {
+                lines_per_level: u32::MAX,
+                player_line_clear: false,
+                goal: Goal::Garbage(*rows),
+                garbage_messiness: messiness.chance(),
+                ..GameMode::Marathon.rules()
+            },
+            GameMode::Zen => Rules {
+                // Looser than one bag, so the pieces feel less scripted when
+                // there's nothing to race.
+                generator: GeneratorKind::FourteenBag,
+                lines_per_level: u32::MAX,
+                // No clock to race, so there's time to watch the rows go.
+                line_clear_delay: 20.0,
+                top_out: TopOut::Compress,
+                ..GameMode::Marathon.rules()
+            },
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::menu::update_menu_widget;
+use crate::menu::MenuEntry;
+use crate::menu::MenuEvent;
+use crate::util::delta_time;
+use crate::Game;
+use crate::GameState;
+use crate::COLORS;
+
+fn mode_entries(game: &Game) -> Vec<MenuEntry> {
+    game.mode_choices.iter()
+        .map(|mode| MenuEntry::new(&mode.label()))
+        .collect()
+}
+
+pub fn update_mode_select(game: &mut Game) {
+    if game.play {
+        game.menu_delay -= delta_time();
+        if game.menu_delay <= 0.0 {
+            game.state = GameState::Game;
+            game.new_game();
+            game.play_music();
+        }
+        return;
+    }
+
+    let entries = mode_entries(game);
+    match update_menu_widget(game, |game| &mut game.mode_menu, &entries) {
+        Some(MenuEvent::Adjust(i, direction)) => game.mode_choices[i] = game.mode_choices[i].cycle(direction),
+        Some(MenuEvent::Select(i)) => {
+            game.mode = game.mode_choices[i];
+            game.play = true;
+            game.menu_delay = 30.0;
+            game.shake();
+            game.play_effect(game.play_sfx, 0.9);
+        },
+        Some(MenuEvent::Back) => game.state = GameState::Menu,
+        _ => {},
+    }
+}
+
+pub fn render_mode_select(game: &Game) {
+    draw_text("Choose a mode", 70.0, 40.0, 32.0, WHITE);
+    game.mode_menu.render(&mode_entries(game), 40.0, 80.0, 16.0, 0.0);
+    let mode = game.mode_choices[game.mode_menu.selection];
+    draw_text(mode.description(), 40.0, 200.0, 16.0, COLORS[2]);
+    if mode.variants().len() > 1 {
+        let bindings = &game.settings.bindings;
+        draw_text(
+            &format!("{}/{}: change", bindings.describe(Action::MenuLeft), bindings.describe(Action::MenuRight)),
+            40.0,
+            216.0,
+            12.0,
+            WHITE,
+        );
+    }
+}
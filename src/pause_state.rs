@@ -0,0 +1,84 @@
+use macroquad::audio::stop_sound;
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::game_state::render_board;
+use crate::game_state::render_score;
+use crate::menu::update_menu_widget;
+use crate::menu::MenuEntry;
+use crate::menu::MenuEvent;
+use crate::mode::GameMode;
+use crate::Game;
+use crate::GameState;
+
+fn pause_entries() -> Vec<MenuEntry> {
+    ["Resume", "Restart", "Settings", "Controls", "Quit to menu"]
+        .iter()
+        .map(|label| MenuEntry::new(label))
+        .collect()
+}
+
+/// Stops the game where it is. Nothing in the engine moves until it's resumed,
+/// so timers and gravity pick up exactly where they left off.
+pub fn pause(game: &mut Game) {
+    game.state = GameState::Paused;
+    game.pause_menu.selection = 0;
+    game.mute_music(true);
+}
+
+fn resume(game: &mut Game) {
+    game.state = GameState::Game;
+    game.mute_music(false);
+}
+
+pub fn update_pause(game: &mut Game) {
+    if game.is_pressed(Action::Pause) {
+        resume(game);
+        return;
+    }
+    let entries = pause_entries();
+    match update_menu_widget(game, |game| &mut game.pause_menu, &entries) {
+        Some(MenuEvent::Select(i)) => match entries[i].label.as_str() {
+            "Resume" => resume(game),
+            "Restart" => {
+                game.save_replay();
+                game.new_game();
+                game.shake();
+                stop_sound(game.music.unwrap());
+                game.play_music();
+                game.state = GameState::Game;
+            },
+            "Settings" => {
+                game.screen_return = GameState::Paused;
+                game.state = GameState::Settings;
+            },
+            "Controls" => {
+                game.controls_menu.selection = 0;
+                game.screen_return = GameState::Paused;
+                game.state = GameState::Controls;
+            },
+            _ => {
+                game.save_replay();
+                stop_sound(game.music.unwrap());
+                leave_game(game);
+            },
+        },
+        Some(MenuEvent::Back) => resume(game),
+        _ => {},
+    }
+}
+
+/// Goes back to the menu, by way of the session summary in Zen.
+pub fn leave_game(game: &mut Game) {
+    game.play = false;
+    game.menu_delay = 30.0;
+    game.state = if game.mode == GameMode::Zen { GameState::Summary } else { GameState::Menu };
+}
+
+/// Only the empty well is drawn while paused, so a pause can't be used to
+/// study the stack or plan the next pieces.
+pub fn render_pause(game: &Game) {
+    render_board(game, false);
+    render_score(game);
+    draw_text("PAUSED", 48.0, 60.0, 32.0, WHITE);
+    game.pause_menu.render(&pause_entries(), 40.0, 90.0, 16.0, 0.0);
+}
@@ -0,0 +1,461 @@
+use macroquad::miniquad::date;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use crate::engine::Buttons;
+use crate::engine::Engine;
+use crate::engine::Event;
+use crate::engine::Input;
+use crate::mode::GameMode;
+use crate::settings::Handling;
+use crate::util::read_config_file;
+use crate::util::today;
+use crate::util::write_config_file;
+
+const REPLAY_VERSION: i64 = 2;
+/// How many of the latest games are kept. Each one has its own file.
+pub const REPLAY_SLOTS: usize = 10;
+/// The longest replay that will be read, four hours at 60 frames a second,
+/// so a corrupt file can't ask for more memory than there is.
+const MAX_FRAMES: usize = 4 * 60 * 60 * 60;
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// One engine update: what was held and pressed, and how long it was.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Frame {
+    pub input: Input,
+    pub dt: f32,
+}
+
+/// Handling settings changed from the pause menu, taking effect from `frame`.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct HandlingChange {
+    pub frame: usize,
+    pub handling: Handling,
+}
+
+/// Everything needed to play a game again: the engine is deterministic, so
+/// the seed and every frame's input are enough to get the exact same game.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: i64,
+    pub seed: u64,
+    /// When the game was played, as YYYY-MM-DD.
+    pub date: String,
+    /// When the replay was saved, in seconds since 1970, so the oldest one
+    /// can be replaced.
+    pub saved_at: f64,
+    pub score: u32,
+    pub lines: u32,
+    /// Seconds the game lasted.
+    pub time: f32,
+    #[serde(serialize_with = "serialize_frames", deserialize_with = "deserialize_frames")]
+    pub frames: Vec<Frame>,
+    pub mode: GameMode,
+    pub handling: Handling,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub handling_changes: Vec<HandlingChange>,
+}
+
+impl Replay {
+    /// Starts recording a new game.
+    pub fn new(mode: GameMode, seed: u64, handling: Handling) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            date: today(),
+            saved_at: 0.0,
+            score: 0,
+            lines: 0,
+            time: 0.0,
+            frames: Vec::new(),
+            mode,
+            handling,
+            handling_changes: Vec::new(),
+        }
+    }
+
+    /// Adds a frame. Past `MAX_FRAMES` the replay just stops, so it can
+    /// still be read.
+    pub fn record(&mut self, input: Input, dt: f32) {
+        if self.frames.len() < MAX_FRAMES {
+            self.frames.push(Frame { input, dt });
+        }
+    }
+
+    pub fn change_handling(&mut self, handling: Handling) {
+        self.handling_changes.push(HandlingChange {
+            frame: self.frames.len(),
+            handling,
+        });
+    }
+
+    /// A fresh engine for the game, before its first frame.
+    pub fn engine(&self) -> Engine {
+        Engine::new(self.mode.rules(), self.handling, self.seed)
+    }
+
+    /// Saves the replay with the result of `engine`, over the oldest one if
+    /// every slot is taken. Games that never started aren't kept.
+    pub fn save(&mut self, engine: &Engine) -> Result<(), String> {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+        self.score = engine.score.score;
+        self.lines = engine.score.lines;
+        self.time = (engine.frames / 60.0) as f32;
+        self.saved_at = date::now();
+
+        let saved_at: Vec<f64> = (0..REPLAY_SLOTS)
+            .map(|slot| Replay::load(slot).map(|replay| replay.saved_at).unwrap_or(0.0))
+            .collect();
+        let slot = (0..REPLAY_SLOTS).min_by(|&a, &b| saved_at[a].total_cmp(&saved_at[b])).unwrap();
+        let contents = toml::to_string(self).map_err(|error| error.to_string())?;
+        write_config_file(&slot_file(slot), &contents)
+    }
+
+    fn load(slot: usize) -> Option<Replay> {
+        Replay::parse(&read_config_file(&slot_file(slot))?)
+    }
+
+    /// Reads a saved replay, upgrading it if it's from an older version.
+    fn parse(contents: &str) -> Option<Replay> {
+        let mut table: toml::Table = contents.parse().ok()?;
+        let version = table.get("version")
+            .and_then(|version| version.as_integer())
+            .unwrap_or(0);
+        if version > REPLAY_VERSION {
+            return None;
+        }
+        Replay::migrate(&mut table, version);
+        table.try_into().ok()
+    }
+
+    /// Upgrades a replay table saved by an older version of the game.
+    fn migrate(table: &mut toml::Table, version: i64) {
+        // Dig races had no choice of messiness before version 2, and were
+        // all messy.
+        if version < 2 {
+            if let Some(toml::Value::Table(mode)) = table.get_mut("mode") {
+                if let Some(rows) = mode.remove("Dig") {
+                    let messiness = toml::Value::String("messy".to_string());
+                    mode.insert("Dig".to_string(), toml::Value::Array(vec![rows, messiness]));
+                }
+            }
+        }
+        table.insert("version".to_string(), toml::Value::Integer(REPLAY_VERSION));
+    }
+
+    /// Every saved replay that can be read, newest first.
+    pub fn load_all() -> Vec<Replay> {
+        let mut replays: Vec<Replay> = (0..REPLAY_SLOTS).filter_map(Replay::load).collect();
+        replays.sort_by(|a, b| b.saved_at.total_cmp(&a.saved_at));
+        replays
+    }
+}
+
+fn slot_file(slot: usize) -> String {
+    format!("replay_{}.toml", slot)
+}
+
+/// Plays a replay through an engine, which can be paused, sped up, stepped
+/// and seeked.
+pub struct Playback {
+    pub replay: Replay,
+    /// How many frames have been played.
+    pub frame: usize,
+    pub paused: bool,
+    pub speed: f32,
+    /// Frames owed at speeds that aren't whole numbers.
+    progress: f32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            frame: 0,
+            paused: false,
+            speed: 1.0,
+            progress: 0.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frames.len()
+    }
+
+    /// Plays the next frame, if there is one.
+    pub fn step(&mut self, engine: &mut Engine) -> Vec<Event> {
+        let Some(&frame) = self.replay.frames.get(self.frame) else {
+            return Vec::new();
+        };
+        for change in self.replay.handling_changes.iter().filter(|change| change.frame == self.frame) {
+            engine.handling = change.handling;
+        }
+        self.frame += 1;
+        engine.update(frame.input, frame.dt)
+    }
+
+    /// Plays a tick's worth of the replay at the current speed.
+    pub fn update(&mut self, engine: &mut Engine) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.paused {
+            return events;
+        }
+        self.progress += self.speed;
+        while self.progress >= 1.0 {
+            self.progress -= 1.0;
+            events.extend(self.step(engine));
+        }
+        events
+    }
+
+    /// Jumps to just after `frame`. Going backwards plays the game again from
+    /// the start, since the engine can't be rewound.
+    pub fn seek(&mut self, engine: &mut Engine, frame: usize) {
+        let frame = frame.min(self.replay.frames.len());
+        if frame < self.frame {
+            *engine = self.replay.engine();
+            self.frame = 0;
+        }
+        while self.frame < frame {
+            self.step(engine);
+        }
+        self.progress = 0.0;
+    }
+}
+
+fn buttons_to_bits(buttons: Buttons) -> u8 {
+    [
+        buttons.left,
+        buttons.right,
+        buttons.soft_drop,
+        buttons.hard_drop,
+        buttons.rotate_cw,
+        buttons.rotate_ccw,
+        buttons.rotate_180,
+        buttons.hold,
+    ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, &down)| bits | (down as u8) << i)
+}
+
+fn buttons_from_bits(bits: u8) -> Buttons {
+    let bit = |i: u8| bits & 1 << i != 0;
+    Buttons {
+        left: bit(0),
+        right: bit(1),
+        soft_drop: bit(2),
+        hard_drop: bit(3),
+        rotate_cw: bit(4),
+        rotate_ccw: bit(5),
+        rotate_180: bit(6),
+        hold: bit(7),
+    }
+}
+
+/// Packs frames as runs of identical ones: the run length (7 bits a byte,
+/// high bit set when more follow), held and pressed as a byte each, then the
+/// frame length's f32 bits. The bytes are base64 encoded so replays can be
+/// kept in text storage.
+fn pack_frames(frames: &[Frame]) -> String {
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < frames.len() {
+        let frame = frames[i];
+        let mut run = frames[i..].iter().take_while(|&&other| other == frame).count();
+        i += run;
+        while run >= 0x80 {
+            bytes.push(run as u8 | 0x80);
+            run >>= 7;
+        }
+        bytes.push(run as u8);
+        bytes.push(buttons_to_bits(frame.input.held));
+        bytes.push(buttons_to_bits(frame.input.pressed));
+        bytes.extend(frame.dt.to_le_bytes());
+    }
+    encode_base64(&bytes)
+}
+
+fn unpack_frames(text: &str) -> Result<Vec<Frame>, String> {
+    let bytes = decode_base64(text)?;
+    let mut frames = Vec::new();
+    let mut bytes = bytes.iter().copied();
+    let truncated = || "frames are cut short".to_string();
+    while let Some(mut byte) = bytes.next() {
+        let mut run = 0;
+        let mut shift = 0;
+        loop {
+            if shift >= usize::BITS - 7 {
+                return Err("a run of frames is too long".to_string());
+            }
+            run |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            byte = bytes.next().ok_or_else(truncated)?;
+        }
+        let held = buttons_from_bits(bytes.next().ok_or_else(truncated)?);
+        let pressed = buttons_from_bits(bytes.next().ok_or_else(truncated)?);
+        let mut dt = [0; 4];
+        for byte in dt.iter_mut() {
+            *byte = bytes.next().ok_or_else(truncated)?;
+        }
+        let frame = Frame {
+            input: Input { held, pressed },
+            dt: f32::from_le_bytes(dt),
+        };
+        if run > MAX_FRAMES - frames.len() {
+            return Err(format!("more than {} frames", MAX_FRAMES));
+        }
+        frames.resize(frames.len() + run, frame);
+    }
+    Ok(frames)
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(group >> (18 - i * 6) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut group = 0u32;
+    let mut bits = 0;
+    for character in text.bytes().filter(|&character| character != b'=') {
+        let value = BASE64_ALPHABET.iter()
+            .position(|&other| other == character)
+            .ok_or_else(|| format!("unexpected character {:?} in frames", character as char))?;
+        group = group << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((group >> bits) as u8);
+            group &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+fn serialize_frames<S: Serializer>(frames: &[Frame], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&pack_frames(frames))
+}
+
+fn deserialize_frames<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Frame>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    unpack_frames(&text).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::Messiness;
+
+    fn frames(run: usize) -> Vec<Frame> {
+        let held = Buttons { left: true, hold: true, ..Default::default() };
+        let frame = Frame {
+            input: Input { held, pressed: held },
+            dt: 1.0,
+        };
+        let other = Frame {
+            input: Input::default(),
+            dt: 0.5,
+        };
+        let mut frames = vec![frame; run];
+        frames.push(other);
+        frames
+    }
+
+    #[test]
+    fn runs_round_trip_across_the_length_byte_boundary() {
+        for run in [127, 128, 129] {
+            let frames = frames(run);
+            assert_eq!(unpack_frames(&pack_frames(&frames)), Ok(frames));
+        }
+    }
+
+    #[test]
+    fn long_runs_take_another_length_byte() {
+        let short = decode_base64(&pack_frames(&frames(127))).unwrap();
+        let long = decode_base64(&pack_frames(&frames(128))).unwrap();
+        assert_eq!(long.len(), short.len() + 1);
+    }
+
+    #[test]
+    fn no_frames_round_trip() {
+        assert_eq!(pack_frames(&[]), "");
+        assert_eq!(unpack_frames(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn cut_short_frames_are_an_error() {
+        // One run, so every cut lands part way through it.
+        let bytes = decode_base64(&pack_frames(&frames(128)[..128])).unwrap();
+        for length in 1..bytes.len() {
+            assert!(unpack_frames(&encode_base64(&bytes[..length])).is_err(), "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn replays_round_trip_through_toml() {
+        let mut replay = Replay::new(GameMode::Dig(40, Messiness::Chaotic), 7, Handling::default());
+        replay.frames = frames(3);
+        let contents = toml::to_string(&replay).unwrap();
+        assert_eq!(Replay::parse(&contents), Some(replay));
+    }
+
+    #[test]
+    fn version_1_dig_replays_are_messy() {
+        let mut replay = Replay::new(GameMode::Dig(10, Messiness::Messy), 7, Handling::default());
+        replay.frames = frames(3);
+        let contents = toml::to_string(&replay).unwrap()
+            .replace("version = 2", "version = 1")
+            .replace("Dig = [10, \"messy\"]", "Dig = 10");
+        assert!(contents.contains("Dig = 10"), "{}", contents);
+        assert_eq!(Replay::parse(&contents), Some(replay));
+    }
+
+    /// One run of `run` empty frames, packed by hand so it can claim any
+    /// length.
+    fn run_bytes(mut run: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while run >= 0x80 {
+            bytes.push(run as u8 | 0x80);
+            run >>= 7;
+        }
+        bytes.extend([run as u8, 0, 0]);
+        bytes.extend(1.0f32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn overlong_run_lengths_are_an_error() {
+        let mut bytes = vec![0xff; 12];
+        bytes.extend(run_bytes(1));
+        assert!(unpack_frames(&encode_base64(&bytes)).is_err());
+    }
+
+    #[test]
+    fn huge_runs_are_an_error() {
+        assert!(unpack_frames(&encode_base64(&run_bytes(usize::MAX >> 8))).is_err());
+        assert!(unpack_frames(&encode_base64(&run_bytes(MAX_FRAMES + 1))).is_err());
+        let mut bytes = run_bytes(MAX_FRAMES / 2 + 1);
+        bytes.extend(run_bytes(MAX_FRAMES / 2));
+        assert!(unpack_frames(&encode_base64(&bytes)).is_err());
+        assert_eq!(unpack_frames(&encode_base64(&run_bytes(MAX_FRAMES))).map(|frames| frames.len()), Ok(MAX_FRAMES));
+    }
+}
@@ -0,0 +1,168 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::engine::Event;
+use crate::game_state::render_game;
+use crate::line_clear::start_line_clear;
+use crate::line_clear::update_line_clears;
+use crate::menu::play_menu_sound;
+use crate::menu::update_menu_widget;
+use crate::menu::MenuEntry;
+use crate::menu::MenuEvent;
+use crate::mode::Ranking;
+use crate::replay::Playback;
+use crate::replay::Replay;
+use crate::sprites::Sprites;
+use crate::util::format_time;
+use crate::Game;
+use crate::GameState;
+use crate::SCREEN_WIDTH;
+use crate::COLORS;
+
+/// How far left and right seek while playing, about five seconds.
+const SEEK_FRAMES: usize = 300;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.0;
+
+fn replay_entries(game: &Game) -> Vec<MenuEntry> {
+    game.replays.iter()
+        .map(|replay| {
+            let result = match replay.mode.ranking() {
+                Ranking::Score => replay.score.to_string(),
+                Ranking::Time => format_time(replay.time),
+            };
+            MenuEntry::with_value(&format!("{} {}", replay.date, replay.mode.label()), result)
+        })
+        .collect()
+}
+
+pub fn update_replays(game: &mut Game) {
+    let entries = replay_entries(game);
+    match update_menu_widget(game, |game| &mut game.replays_menu, &entries) {
+        Some(MenuEvent::Select(i)) => watch(game, game.replays[i].clone()),
+        Some(MenuEvent::Back) => game.state = GameState::Menu,
+        _ => {},
+    }
+}
+
+fn watch(game: &mut Game, replay: Replay) {
+    game.mode = replay.mode;
+    game.engine = replay.engine();
+    game.sprites = Sprites::new(&game.engine);
+    game.name_entry = None;
+    game.new_record = None;
+    game.playback = Some(Playback::new(replay));
+    game.state = GameState::Playback;
+}
+
+pub fn render_replays(game: &Game) {
+    draw_text("Replays", 40.0, 20.0, 24.0, WHITE);
+    if game.replays.is_empty() {
+        draw_text("No replays yet!", 0.0, 50.0, 16.0, WHITE);
+    }
+    game.replays_menu.render(&replay_entries(game), 0.0, 50.0, 16.0, 190.0);
+    let bindings = &game.settings.bindings;
+    draw_text(
+        &format!(
+            "{}/{}: speed  {}/{}: seek or step  {}: pause",
+            bindings.describe(Action::MenuUp),
+            bindings.describe(Action::MenuDown),
+            bindings.describe(Action::MenuLeft),
+            bindings.describe(Action::MenuRight),
+            bindings.describe(Action::Confirm),
+        ),
+        -8.0,
+        234.0,
+        12.0,
+        WHITE,
+    );
+    draw_text(
+        &format!("{}: watch  {}: back", bindings.describe(Action::Confirm), bindings.describe(Action::Back)),
+        -8.0,
+        248.0,
+        12.0,
+        WHITE,
+    );
+}
+
+pub fn update_playback(game: &mut Game) {
+    let Some(mut playback) = game.playback.take() else {
+        game.state = GameState::Replays;
+        return;
+    };
+    if game.is_pressed(Action::Back) {
+        play_menu_sound(game, MenuEvent::Back);
+        game.state = GameState::Replays;
+        return;
+    }
+
+    if game.is_pressed(Action::Confirm)
+    || game.is_pressed(Action::Pause) {
+        if playback.is_finished() {
+            playback.seek(&mut game.engine, 0);
+            playback.paused = false;
+        } else {
+            playback.paused = !playback.paused;
+        }
+    }
+    if game.is_pressed(Action::MenuUp) {
+        playback.speed = (playback.speed * 2.0).min(MAX_SPEED);
+    }
+    if game.is_pressed(Action::MenuDown) {
+        playback.speed = (playback.speed / 2.0).max(MIN_SPEED);
+    }
+    // Paused, left and right step a single frame instead.
+    let distance = if playback.paused { 1 } else { SEEK_FRAMES };
+    if game.is_pressed(Action::MenuLeft) {
+        let frame = playback.frame.saturating_sub(distance);
+        playback.seek(&mut game.engine, frame);
+    }
+    if game.is_pressed(Action::MenuRight) {
+        let frame = playback.frame + distance;
+        playback.seek(&mut game.engine, frame);
+    }
+
+    game.playback = Some(playback);
+}
+
+/// Plays a tick's worth of the replay at the current speed.
+pub fn tick_playback(game: &mut Game) {
+    game.sprites.ease(&game.engine);
+    update_line_clears(game);
+    let Some(playback) = game.playback.as_mut() else {
+        return;
+    };
+    for event in playback.update(&mut game.engine) {
+        game.sprites.handle_event(&game.engine, event);
+        match event {
+            Event::HardDrop => game.shake(),
+            Event::LinesCleared(lines) => start_line_clear(game, lines),
+            _ => {},
+        }
+    }
+}
+
+pub fn render_playback(game: &Game) {
+    render_game(game);
+    let Some(playback) = &game.playback else {
+        return;
+    };
+    let frames = playback.replay.frames.len().max(1);
+    draw_rectangle(-16.0, 0.0, SCREEN_WIDTH as f32 * playback.frame as f32 / frames as f32, 3.0, COLORS[1]);
+
+    let x = game.layout().side_x + 4.0;
+    let status = if playback.is_finished() {
+        "Replay over".to_string()
+    } else if playback.paused {
+        "Paused".to_string()
+    } else {
+        format!("Replay {}x", playback.speed)
+    };
+    draw_text(&status, x, 16.0, 16.0, WHITE);
+    draw_text(
+        &format!("{} / {}", format_time((game.engine.frames / 60.0) as f32), format_time(playback.replay.time)),
+        x,
+        30.0,
+        12.0,
+        COLORS[2],
+    );
+}
@@ -0,0 +1,92 @@
+use macroquad::math::vec2;
+use macroquad::math::Vec2;
+use crate::block::Block;
+use crate::block::BlockShape;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Rotation {
+    Clockwise,
+    CounterClockwise,
+    Half,
+}
+
+impl Rotation {
+    /// How many clockwise quarter turns this rotation is.
+    pub fn turns(&self) -> u8 {
+        match self {
+            Rotation::Clockwise => 1,
+            Rotation::Half => 2,
+            Rotation::CounterClockwise => 3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RotationSystem {
+    /// Super Rotation System, the guideline standard.
+    Srs,
+    /// Arika style: nudge one cell right, then left, and the I piece never kicks.
+    Ars,
+    /// The rotation either fits where it is or doesn't happen.
+    Classic,
+}
+
+// Wall kicks for J, L, S, T and Z, from the SRS tables with y pointing up,
+// indexed by the state being rotated from. Clockwise turns use the first table
+// and counter clockwise turns the second.
+const JLSTZ_CW_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+const JLSTZ_CCW_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+const I_CW_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+const I_CCW_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+];
+// SRS has no 180 table, so this is the usual small one: up, sideways, down.
+const HALF_KICKS: [(i32, i32); 5] = [(0, 0), (0, 1), (1, 0), (-1, 0), (0, -1)];
+
+impl RotationSystem {
+    /// Offsets to try, in order, when turning `block` by `rotation`. The first
+    /// one that doesn't collide wins, and if none fit the rotation fails.
+    pub fn kicks(&self, block: &Block, rotation: Rotation) -> Vec<Vec2> {
+        let kicks: &[(i32, i32)] = match self {
+            RotationSystem::Srs => {
+                let state = block.srs_state() as usize;
+                match (block.block_shape, rotation) {
+                    (BlockShape::O, _) => &[(0, 0)],
+                    (_, Rotation::Half) => &HALF_KICKS,
+                    (BlockShape::I, Rotation::Clockwise) => &I_CW_KICKS[state],
+                    (BlockShape::I, Rotation::CounterClockwise) => &I_CCW_KICKS[state],
+                    (_, Rotation::Clockwise) => &JLSTZ_CW_KICKS[state],
+                    (_, Rotation::CounterClockwise) => &JLSTZ_CCW_KICKS[state],
+                }
+            },
+            RotationSystem::Ars => {
+                match block.block_shape {
+                    BlockShape::I | BlockShape::O => &[(0, 0)],
+                    _ => &[(0, 0), (1, 0), (-1, 0)],
+                }
+            },
+            RotationSystem::Classic => &[(0, 0)],
+        };
+        kicks.iter()
+            .map(|&(x, y)| vec2(x as f32, -y as f32))
+            .collect()
+    }
+}
@@ -0,0 +1,91 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::menu::play_menu_sound;
+use crate::menu::MenuEvent;
+use crate::mode::GameMode;
+use crate::mode::Ranking;
+use crate::util::format_time;
+use crate::util::format_time_precise;
+use crate::Game;
+use crate::GameState;
+use crate::COLORS;
+
+pub fn update_scores(game: &mut Game) {
+    if game.is_pressed(Action::Back)
+    || game.is_pressed(Action::Confirm) {
+        play_menu_sound(game, MenuEvent::Back);
+        game.state = GameState::Menu;
+    } else if game.is_pressed(Action::MenuLeft)
+    || game.is_pressed(Action::MenuRight) {
+        let mut tables: Vec<GameMode> = GameMode::ALL.iter()
+            .filter(|mode| mode.has_high_scores())
+            .flat_map(|mode| mode.variants())
+            .collect();
+        // Some variants share a table.
+        tables.dedup_by_key(|mode| mode.name());
+        let direction = if game.is_pressed(Action::MenuLeft) { tables.len() - 1 } else { 1 };
+        let index = tables.iter().position(|mode| mode.name() == game.mode.name()).unwrap_or(0);
+        game.mode = tables[(index + direction) % tables.len()];
+        play_menu_sound(game, MenuEvent::Moved);
+    }
+}
+
+pub fn render_scores(game: &Game) {
+    draw_text(&format!("High scores: {}", game.mode.table_label()), 40.0, 20.0, 24.0, WHITE);
+    render_score_table(game, game.mode, -14.0, 50.0, false, None);
+    let bindings = &game.settings.bindings;
+    draw_text(
+        &format!("{}/{}: change mode  {}: back", bindings.describe(Action::MenuLeft), bindings.describe(Action::MenuRight), bindings.describe(Action::Back)),
+        -8.0,
+        248.0,
+        12.0,
+        WHITE,
+    );
+}
+
+/// Draws a mode's high score table from `(x, y)`. The compact version only
+/// has names and scores (or times) so it fits over the board. `highlight` is
+/// the rank of a score that was just set.
+pub fn render_score_table(game: &Game, mode: GameMode, x: f32, y: f32, compact: bool, highlight: Option<usize>) {
+    let table = game.high_scores.table(mode);
+    if table.is_empty() {
+        draw_text("No scores yet!", x, y, 16.0, WHITE);
+        return;
+    }
+    let timed = mode.ranking() == Ranking::Time;
+    for (i, entry) in table.iter().enumerate() {
+        let row_y = y + i as f32 * 14.0;
+        let color = if highlight == Some(i) { COLORS[1] } else { WHITE };
+        draw_text(&format!("{}.", i + 1), x, row_y, 16.0, color);
+        draw_text(&entry.name, x + 22.0, row_y, 16.0, color);
+        if timed {
+            draw_text(&format_time_precise(entry.time), x + 100.0, row_y, 16.0, color);
+        } else {
+            draw_text(&entry.score.to_string(), x + 100.0, row_y, 16.0, color);
+        }
+        if !compact {
+            if timed {
+                draw_text(&entry.score.to_string(), x + 175.0, row_y, 16.0, color);
+            } else {
+                let progress = match &entry.grade {
+                    Some(grade) => format!("{}/{}", grade, entry.level),
+                    None => format!("{}/{}", entry.lines, entry.level),
+                };
+                draw_text(&progress, x + 155.0, row_y, 16.0, color);
+                draw_text(&format_time(entry.time), x + 200.0, row_y, 16.0, color);
+            }
+            draw_text(&entry.date, x + 255.0, row_y, 12.0, color);
+        }
+    }
+    if !compact {
+        let headers = if timed {
+            vec![("Name", 22.0), ("Time", 100.0), ("Score", 175.0), ("Date", 255.0)]
+        } else {
+            let progress = if mode == GameMode::Master { "Grade/Lv" } else { "Lines/Lv" };
+            vec![("Name", 22.0), ("Score", 100.0), (progress, 155.0), ("Time", 200.0), ("Date", 255.0)]
+        };
+        for (header, offset) in headers {
+            draw_text(header, x + offset, y - 14.0, 12.0, COLORS[2]);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+/// How points and levels are worked out.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ScoringSystem {
+    /// T-spins, back-to-backs and combos, with points for hard drops.
+    Guideline,
+    /// Points for line clears only (and soft drops), and the first level up
+    /// comes later when starting on a high level.
+    Nes,
+    /// TGM: every piece and every line is a level, and levels stop at each
+    /// section's 99 until a line is cleared. Clears score more on higher
+    /// levels and in combos, plus the rows soft dropped.
+    Tgm,
+}
+
+/// Grades from worst to best, as reached in Master.
+pub const GRADES: [&str; 19] = [
+    "9", "8", "7", "6", "5", "4", "3", "2", "1",
+    "S1", "S2", "S3", "S4", "S5", "S6", "S7", "S8", "S9", "GM",
+];
+/// The score needed for each grade up to S9. GM takes more than a score.
+const GRADE_SCORES: [u32; 18] = [
+    0, 400, 800, 1400, 2000, 3500, 5500, 8000, 12000,
+    16000, 22000, 30000, 40000, 52000, 66000, 82000, 100000, 120000,
+];
+/// Levels are counted up to this in TGM scoring.
+pub const MAX_TGM_LEVEL: u32 = 999;
+
+/// Score, level and line count for one game.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Score {
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// How many clears in a row, -1 when the last piece cleared nothing.
+    pub combo: i32,
+    /// Whether the last clear was a tetris or T-spin, which makes the next one
+    /// worth half again as much.
+    pub back_to_back: bool,
+    lines_per_level: u32,
+    start_level: u32,
+    system: ScoringSystem,
+    /// TGM's combo multiplier, which grows by more for bigger clears.
+    tgm_combo: u32,
+    /// Rows soft or hard dropped by the current piece, which TGM scores on
+    /// locking.
+    soft_drop_rows: u32,
+}
+
+impl Score {
+    pub fn new(level: u32, lines_per_level: u32, system: ScoringSystem) -> Score {
+        Score {
+            score: 0,
+            lines: 0,
+            level,
+            combo: -1,
+            back_to_back: false,
+            lines_per_level,
+            start_level: level,
+            system,
+            tgm_combo: 1,
+            soft_drop_rows: 0,
+        }
+    }
+
+    pub fn soft_drop(&mut self, cells: u32) {
+        if self.system == ScoringSystem::Tgm {
+            self.soft_drop_rows += cells;
+        } else {
+            self.score += cells;
+        }
+    }
+
+    pub fn hard_drop(&mut self, cells: u32) {
+        if self.system == ScoringSystem::Tgm {
+            self.soft_drop_rows += cells;
+        } else {
+            self.score += cells * 2;
+        }
+    }
+
+    /// Scores a locked piece that cleared `lines` rows. Returns true if the
+    /// level went up.
+    pub fn lock(&mut self, lines: u32, t_spin: bool) -> bool {
+        match self.system {
+            ScoringSystem::Guideline => {},
+            ScoringSystem::Nes => return self.lock_nes(lines),
+            ScoringSystem::Tgm => return self.lock_tgm(lines),
+        }
+        if lines == 0 {
+            self.combo = -1;
+            if t_spin {
+                self.score += 400 * self.level;
+            }
+            return false;
+        }
+
+        let base = if t_spin {
+            match lines {
+                1 => 800,
+                2 => 1200,
+                _ => 1600,
+            }
+        } else {
+            match lines {
+                1 => 100,
+                2 => 300,
+                3 => 500,
+                _ => 800,
+            }
+        };
+        let difficult = t_spin || lines >= 4;
+        let mut points = base * self.level;
+        if difficult && self.back_to_back {
+            points += points / 2;
+        }
+        self.back_to_back = difficult;
+
+        self.combo += 1;
+        points += 50 * self.combo as u32 * self.level;
+        self.score += points;
+
+        let old_level = self.level;
+        self.lines += lines;
+        self.level = self.level.max(self.start_level + self.lines / self.lines_per_level);
+        self.level > old_level
+    }
+
+    fn lock_nes(&mut self, lines: u32) -> bool {
+        let base = match lines {
+            0 => 0,
+            1 => 40,
+            2 => 100,
+            3 => 300,
+            _ => 1200,
+        };
+        self.score += base * (self.level + 1);
+
+        let old_level = self.level;
+        self.lines += lines;
+        // Starting on level 10 or higher holds off the first level up until
+        // 100 lines, or more from level 16.
+        let first_level_up = (self.start_level * 10 + 10).min(100.max((self.start_level * 10).saturating_sub(50)));
+        if self.lines >= first_level_up {
+            self.level = self.level.max(self.start_level + 1 + (self.lines - first_level_up) / self.lines_per_level);
+        }
+        self.level > old_level
+    }
+
+    fn lock_tgm(&mut self, lines: u32) -> bool {
+        let soft_drop_rows = std::mem::take(&mut self.soft_drop_rows);
+        if lines == 0 {
+            self.tgm_combo = 1;
+            self.combo = -1;
+            return false;
+        }
+        self.tgm_combo += 2 * lines - 2;
+        self.combo += 1;
+        self.score += ((self.level + lines).div_ceil(4) + soft_drop_rows) * lines * self.tgm_combo;
+
+        let old_level = self.level;
+        self.lines += lines;
+        self.level = (self.level + lines).min(MAX_TGM_LEVEL);
+        self.level / self.lines_per_level > old_level / self.lines_per_level
+    }
+
+    /// Counts a new piece in TGM scoring, where the level goes up by one for
+    /// every piece except at a section's 99 (or at 998).
+    pub fn piece_spawned(&mut self) {
+        if self.system == ScoringSystem::Tgm
+        && self.level % 100 != 99
+        && self.level < MAX_TGM_LEVEL - 1 {
+            self.level += 1;
+        }
+    }
+
+    /// The level the current section stops at until a line is cleared.
+    pub fn section_stop(&self) -> u32 {
+        (self.level / 100 * 100 + 99).min(MAX_TGM_LEVEL)
+    }
+
+    /// The grade earned by the score so far, as an index into `GRADES`.
+    pub fn grade(&self) -> usize {
+        GRADE_SCORES.iter().rposition(|&score| self.score >= score).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_to_back_tetrises_and_combos_score_extra() {
+        let mut score = Score::new(1, 10, ScoringSystem::Guideline);
+        score.lock(4, false);
+        assert_eq!(score.score, 800);
+        // Half again for the back to back, and 50 for the combo.
+        score.lock(4, false);
+        assert_eq!(score.score, 800 + 1200 + 50);
+        // A single breaks the back to back but keeps the combo going.
+        score.lock(1, false);
+        assert_eq!(score.score, 2050 + 100 + 100);
+        assert!(!score.back_to_back);
+        score.lock(0, false);
+        assert_eq!(score.combo, -1);
+        score.lock(1, false);
+        assert_eq!(score.score, 2250 + 100);
+    }
+
+    #[test]
+    fn t_spins_keep_the_back_to_back() {
+        let mut score = Score::new(2, 10, ScoringSystem::Guideline);
+        score.lock(4, false);
+        score.lock(0, false);
+        score.lock(2, true);
+        assert_eq!(score.score, 800 * 2 + 1800 * 2);
+        assert!(score.back_to_back);
+    }
+
+    /// The line count on which a Classic game first levels up, clearing a
+    /// single at a time.
+    fn first_level_up(start_level: u32) -> u32 {
+        let mut score = Score::new(start_level, 10, ScoringSystem::Nes);
+        (1..).find(|_| score.lock(1, false)).unwrap()
+    }
+
+    #[test]
+    fn nes_high_start_levels_hold_off_the_first_level_up() {
+        assert_eq!(first_level_up(0), 10);
+        assert_eq!(first_level_up(10), 100);
+        assert_eq!(first_level_up(19), 140);
+    }
+
+    #[test]
+    fn tgm_levels_stop_at_each_section_end() {
+        let mut score = Score::new(0, 100, ScoringSystem::Tgm);
+        for _ in 0..150 {
+            score.piece_spawned();
+        }
+        assert_eq!(score.level, 99);
+        score.level = 997;
+        score.piece_spawned();
+        score.piece_spawned();
+        assert_eq!(score.level, 998);
+    }
+
+    #[test]
+    fn tgm_clears_level_up_past_the_section_stop() {
+        let mut score = Score::new(0, 100, ScoringSystem::Tgm);
+        score.level = 50;
+        assert!(!score.lock(2, false));
+        assert_eq!(score.level, 52);
+        score.level = 99;
+        assert!(score.lock(2, false));
+        assert_eq!(score.level, 101);
+        score.level = 997;
+        score.lock(4, false);
+        assert_eq!(score.level, MAX_TGM_LEVEL);
+    }
+}
@@ -0,0 +1,325 @@
+use serde::Deserialize;
+use serde::Serialize;
+use crate::controls::Bindings;
+use crate::mode::LockReset;
+use crate::util::read_config_file;
+use crate::util::write_config_file;
+
+const SETTINGS_FILE: &str = "settings.toml";
+/// Where a settings file that couldn't be read is moved, so it isn't lost.
+const BACKUP_FILE: &str = "settings.toml.bak";
+/// Bindings were saved on their own before there was a settings file.
+const LEGACY_CONTROLS_FILE: &str = "controls.toml";
+
+/// Bump this when the meaning of an existing field changes, and teach
+/// `Settings::migrate` how to upgrade older files.
+const SETTINGS_VERSION: i64 = 1;
+/// The range of lock delays a player can pick, in frames.
+pub const MIN_LOCK_DELAY: f32 = 5.0;
+pub const MAX_LOCK_DELAY: f32 = 120.0;
+/// The longest line clear delay a player can pick, in frames.
+pub const MAX_LINE_CLEAR_DELAY: f32 = 60.0;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GhostStyle {
+    Off,
+    Outline,
+    Faded,
+}
+
+impl GhostStyle {
+    pub fn next(&self) -> GhostStyle {
+        match self {
+            GhostStyle::Off => GhostStyle::Outline,
+            GhostStyle::Outline => GhostStyle::Faded,
+            GhostStyle::Faded => GhostStyle::Off,
+        }
+    }
+}
+
+/// Volumes from 0 to 1, on top of each sound's own mix level.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Audio {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+        }
+    }
+}
+
+/// How the block responds to being held sideways or down. All times are in
+/// frames (60ths of a second).
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handling {
+    /// Delayed auto shift: how long a direction is held before it repeats.
+    pub das: f32,
+    /// Auto repeat rate: time between repeated moves, 0 goes straight to the wall.
+    pub arr: f32,
+    /// How long auto shift is held back after a rotation.
+    pub das_cut: f32,
+    /// How many times faster than gravity a soft drop falls. Infinity drops
+    /// straight to the floor without locking.
+    pub soft_drop_factor: f32,
+    /// How long a block can sit on the ground, and what gives it more time,
+    /// in the modes that leave it to the player.
+    pub lock_delay: f32,
+    pub lock_reset: LockReset,
+    /// How long cleared rows stay on the board, in the modes that leave it
+    /// to the player. `None` keeps the mode's own, which is also what
+    /// replays from before this setting get.
+    pub line_clear_delay: Option<f32>,
+}
+
+impl Default for Handling {
+    fn default() -> Handling {
+        Handling {
+            das: 10.0,
+            arr: 2.0,
+            das_cut: 0.0,
+            soft_drop_factor: 20.0,
+            lock_delay: 30.0,
+            lock_reset: LockReset::Move,
+            line_clear_delay: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Visuals {
+    pub ghost_style: GhostStyle,
+    /// How many times bigger than the game's pixels the window opens.
+    pub window_scale: i32,
+    /// Hides the score and everything else in Zen, leaving just the board
+    /// and the music.
+    pub zen_music_only: bool,
+}
+
+impl Default for Visuals {
+    fn default() -> Visuals {
+        Visuals {
+            ghost_style: GhostStyle::Faded,
+            window_scale: 3,
+            zen_music_only: false,
+        }
+    }
+}
+
+/// Player preferences that change how the game looks and feels. The lock
+/// delay, lock reset and line clear delay in `handling` stand in for the
+/// mode's rules, but only in modes that leave them to the player. Anything
+/// missing from the file gets its default.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: i64,
+    pub audio: Audio,
+    pub handling: Handling,
+    pub visuals: Visuals,
+    pub bindings: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            version: SETTINGS_VERSION,
+            audio: Audio::default(),
+            handling: Handling::default(),
+            visuals: Visuals::default(),
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file. Anything wrong with it falls back to defaults,
+    /// along with a message for the player saying what happened.
+    pub fn load() -> (Settings, Option<String>) {
+        let contents = match read_config_file(SETTINGS_FILE) {
+            Some(contents) => contents,
+            None => return (Settings::from_legacy_controls(), None),
+        };
+        let (settings, message, unreadable) = Settings::from_contents(&contents);
+        if unreadable {
+            let _ = write_config_file(BACKUP_FILE, &contents);
+        }
+        (settings, message)
+    }
+
+    /// The settings in a file's contents, a message if anything was wrong
+    /// with them, and whether the file couldn't be read at all.
+    fn from_contents(contents: &str) -> (Settings, Option<String>, bool) {
+        match Settings::parse(contents) {
+            Ok((settings, problems)) if problems.is_empty() => (settings, None, false),
+            Ok((settings, problems)) => (
+                settings,
+                Some(format!("Reset invalid settings: {}", problems.join(", "))),
+                false,
+            ),
+            Err(error) => (
+                Settings::default(),
+                Some(format!("Couldn't read {} ({}), using defaults", SETTINGS_FILE, error.replace('\n', " "))),
+                true,
+            ),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let contents = toml::to_string(self).map_err(|error| error.to_string())?;
+        write_config_file(SETTINGS_FILE, &contents)
+    }
+
+    /// Parses a settings file, returning the settings and the names of any
+    /// fields that were out of range and got reset.
+    fn parse(contents: &str) -> Result<(Settings, Vec<&'static str>), String> {
+        let mut table: toml::Table = contents.parse().map_err(|error: toml::de::Error| error.message().to_string())?;
+        let version = table.get("version")
+            .and_then(|version| version.as_integer())
+            .unwrap_or(0);
+        if version > SETTINGS_VERSION {
+            return Err(format!("made by a newer version of the game ({})", version));
+        }
+        Settings::migrate(&mut table, version);
+
+        let mut settings: Settings = table.try_into().map_err(|error: toml::de::Error| error.message().to_string())?;
+        settings.version = SETTINGS_VERSION;
+        let problems = settings.validate();
+        Ok((settings, problems))
+    }
+
+    /// Upgrades a settings table saved by an older version of the game.
+    fn migrate(table: &mut toml::Table, version: i64) {
+        // Version 0 files predate the version field, but are otherwise the same.
+        if version < 1 {
+            table.insert("version".to_string(), toml::Value::Integer(1));
+        }
+    }
+
+    fn from_legacy_controls() -> Settings {
+        let bindings = read_config_file(LEGACY_CONTROLS_FILE)
+            .and_then(|contents| toml::from_str(&contents).ok());
+        Settings {
+            bindings: bindings.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Puts anything out of range back to its default, returning the names of
+    /// what was changed.
+    fn validate(&mut self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        let audio = Audio::default();
+        let handling = Handling::default();
+        let visuals = Visuals::default();
+
+        if !(0.0..=1.0).contains(&self.audio.music_volume) {
+            self.audio.music_volume = audio.music_volume;
+            problems.push("audio.music_volume");
+        }
+        if !(0.0..=1.0).contains(&self.audio.sfx_volume) {
+            self.audio.sfx_volume = audio.sfx_volume;
+            problems.push("audio.sfx_volume");
+        }
+        if !(0.0..=60.0).contains(&self.handling.das) {
+            self.handling.das = handling.das;
+            problems.push("handling.das");
+        }
+        if !(0.0..=60.0).contains(&self.handling.arr) {
+            self.handling.arr = handling.arr;
+            problems.push("handling.arr");
+        }
+        if !(0.0..=60.0).contains(&self.handling.das_cut) {
+            self.handling.das_cut = handling.das_cut;
+            problems.push("handling.das_cut");
+        }
+        if self.handling.soft_drop_factor.is_nan()
+        || self.handling.soft_drop_factor < 1.0 {
+            self.handling.soft_drop_factor = handling.soft_drop_factor;
+            problems.push("handling.soft_drop_factor");
+        }
+        if !(MIN_LOCK_DELAY..=MAX_LOCK_DELAY).contains(&self.handling.lock_delay) {
+            self.handling.lock_delay = handling.lock_delay;
+            problems.push("handling.lock_delay");
+        }
+        if self.handling.lock_reset == LockReset::Gravity {
+            self.handling.lock_reset = handling.lock_reset;
+            problems.push("handling.lock_reset");
+        }
+        if self.handling.line_clear_delay.is_some_and(|delay| !(0.0..=MAX_LINE_CLEAR_DELAY).contains(&delay)) {
+            self.handling.line_clear_delay = handling.line_clear_delay;
+            problems.push("handling.line_clear_delay");
+        }
+        if !(1..=8).contains(&self.visuals.window_scale) {
+            self.visuals.window_scale = visuals.window_scale;
+            problems.push("visuals.window_scale");
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_from_before_the_version_field_are_migrated() {
+        let (settings, problems) = Settings::parse("[handling]\ndas = 8.0\n").unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.handling.das, 8.0);
+        assert_eq!(settings.handling.arr, Handling::default().arr);
+    }
+
+    #[test]
+    fn files_from_a_newer_version_are_refused() {
+        assert!(Settings::parse(&format!("version = {}\n", SETTINGS_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn out_of_range_values_are_reset() {
+        let contents = "version = 1\n\
+            [audio]\nmusic_volume = 3.0\nsfx_volume = 0.5\n\
+            [handling]\nlock_delay = 1000.0\nlock_reset = \"gravity\"\nline_clear_delay = -5.0\n\
+            [visuals]\nwindow_scale = 0\n";
+        let (settings, problems) = Settings::parse(contents).unwrap();
+        assert_eq!(problems, [
+            "audio.music_volume",
+            "handling.lock_delay",
+            "handling.lock_reset",
+            "handling.line_clear_delay",
+            "visuals.window_scale",
+        ]);
+        let defaults = Settings::default();
+        assert_eq!(settings.audio.music_volume, defaults.audio.music_volume);
+        assert_eq!(settings.audio.sfx_volume, 0.5);
+        assert_eq!(settings.handling, defaults.handling);
+        assert_eq!(settings.visuals.window_scale, defaults.visuals.window_scale);
+    }
+
+    #[test]
+    fn empty_files_get_the_defaults() {
+        let (settings, message, unreadable) = Settings::from_contents("");
+        assert_eq!(settings, Settings::default());
+        assert_eq!(message, None);
+        assert!(!unreadable);
+    }
+
+    #[test]
+    fn garbage_files_fall_back_to_the_defaults() {
+        for contents in ["not toml at all {", "audio = 3"] {
+            let (settings, message, unreadable) = Settings::from_contents(contents);
+            assert_eq!(settings, Settings::default());
+            assert!(message.is_some());
+            assert!(unreadable);
+        }
+    }
+}
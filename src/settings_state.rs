@@ -0,0 +1,129 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::menu::update_menu_widget;
+use crate::menu::MenuEntry;
+use crate::menu::MenuEvent;
+use crate::settings::MAX_LINE_CLEAR_DELAY;
+use crate::settings::MAX_LOCK_DELAY;
+use crate::settings::MIN_LOCK_DELAY;
+use crate::util::clamp_range;
+use crate::Game;
+use crate::SCREEN_HEIGHT;
+use crate::SCREEN_WIDTH;
+
+/// The fastest soft drop that's still a speed, past which it becomes instant.
+const MAX_SOFT_DROP_FACTOR: f32 = 40.0;
+
+fn settings_entries(game: &Game) -> Vec<MenuEntry> {
+    let settings = &game.settings;
+    let soft_drop = if settings.handling.soft_drop_factor.is_infinite() {
+        "Instant".to_string()
+    } else {
+        format!("{}x", settings.handling.soft_drop_factor)
+    };
+    let line_clear = match settings.handling.line_clear_delay {
+        Some(delay) => format!("{} frames", delay),
+        None => "Mode's own".to_string(),
+    };
+    vec![
+        MenuEntry::with_value("Music volume", format!("{}%", (settings.audio.music_volume * 100.0).round())),
+        MenuEntry::with_value("Sound volume", format!("{}%", (settings.audio.sfx_volume * 100.0).round())),
+        MenuEntry::with_value("DAS", format!("{} frames", settings.handling.das)),
+        MenuEntry::with_value("ARR", format!("{} frames", settings.handling.arr)),
+        MenuEntry::with_value("DAS cut", format!("{} frames", settings.handling.das_cut)),
+        MenuEntry::with_value("Soft drop", soft_drop),
+        MenuEntry::with_value("Lock delay", format!("{} frames", settings.handling.lock_delay)),
+        MenuEntry::with_value("Lock reset", format!("{:?}", settings.handling.lock_reset)),
+        MenuEntry::with_value("Line clear", line_clear),
+        MenuEntry::with_value("Ghost", format!("{:?}", settings.visuals.ghost_style)),
+        MenuEntry::with_value("Window scale", format!("{}x", settings.visuals.window_scale)),
+        MenuEntry::with_value("Zen HUD", if settings.visuals.zen_music_only { "Music only" } else { "Full" }.to_string()),
+        MenuEntry::new("Back"),
+    ]
+}
+
+pub fn update_settings(game: &mut Game) {
+    let entries = settings_entries(game);
+    match update_menu_widget(game, |game| &mut game.settings_menu, &entries) {
+        Some(MenuEvent::Select(i)) if entries[i].label == "Back" => leave_settings(game),
+        Some(MenuEvent::Select(i)) => adjust(game, &entries[i].label, 1),
+        Some(MenuEvent::Adjust(i, direction)) => adjust(game, &entries[i].label, direction),
+        Some(MenuEvent::Back) => leave_settings(game),
+        _ => {},
+    }
+}
+
+/// Steps the setting called `label` one notch in `direction`.
+fn adjust(game: &mut Game, label: &str, direction: i32) {
+    let step = direction as f32;
+    let settings = &mut game.settings;
+    match label {
+        "Music volume" => settings.audio.music_volume = clamp_range(0.0, ((settings.audio.music_volume + step * 0.1) * 10.0).round() / 10.0, 1.0),
+        "Sound volume" => settings.audio.sfx_volume = clamp_range(0.0, ((settings.audio.sfx_volume + step * 0.1) * 10.0).round() / 10.0, 1.0),
+        "DAS" => settings.handling.das = clamp_range(0.0, settings.handling.das + step, 60.0),
+        "ARR" => settings.handling.arr = clamp_range(0.0, settings.handling.arr + step, 60.0),
+        "DAS cut" => settings.handling.das_cut = clamp_range(0.0, settings.handling.das_cut + step, 60.0),
+        "Soft drop" => {
+            let factor = settings.handling.soft_drop_factor;
+            settings.handling.soft_drop_factor = if factor.is_infinite() {
+                if direction < 0 { MAX_SOFT_DROP_FACTOR } else { factor }
+            } else if factor + step > MAX_SOFT_DROP_FACTOR {
+                f32::INFINITY
+            } else {
+                (factor + step).max(1.0)
+            };
+        },
+        "Lock delay" => settings.handling.lock_delay = clamp_range(MIN_LOCK_DELAY, settings.handling.lock_delay + step * 5.0, MAX_LOCK_DELAY),
+        "Lock reset" => {
+            settings.handling.lock_reset = settings.handling.lock_reset.next();
+            if direction < 0 {
+                settings.handling.lock_reset = settings.handling.lock_reset.next();
+            }
+        },
+        "Line clear" => {
+            // Below 0 goes back to the mode's own delay.
+            settings.handling.line_clear_delay = match settings.handling.line_clear_delay {
+                None if direction > 0 => Some(0.0),
+                None => None,
+                Some(delay) if delay + step * 5.0 < 0.0 => None,
+                Some(delay) => Some((delay + step * 5.0).min(MAX_LINE_CLEAR_DELAY)),
+            };
+        },
+        "Ghost" => {
+            settings.visuals.ghost_style = settings.visuals.ghost_style.next();
+            if direction < 0 {
+                settings.visuals.ghost_style = settings.visuals.ghost_style.next();
+            }
+        },
+        "Window scale" => {
+            settings.visuals.window_scale = clamp_range(1, settings.visuals.window_scale + direction, 8);
+            let scale = settings.visuals.window_scale as f32;
+            request_new_screen_size(SCREEN_WIDTH as f32 * scale, SCREEN_HEIGHT as f32 * scale);
+        },
+        "Zen HUD" => settings.visuals.zen_music_only = !settings.visuals.zen_music_only,
+        _ => {},
+    }
+}
+
+fn leave_settings(game: &mut Game) {
+    if game.engine.handling != game.settings.handling {
+        // Replays have to know, to play the rest of the game the same way.
+        game.recording.change_handling(game.settings.handling);
+    }
+    game.engine.handling = game.settings.handling;
+    game.save_settings();
+    game.state = game.screen_return;
+}
+
+pub fn render_settings(game: &Game) {
+    draw_text("Settings", 110.0, 24.0, 32.0, WHITE);
+    game.settings_menu.render(&settings_entries(game), 30.0, 52.0, 15.0, 130.0);
+    let bindings = &game.settings.bindings;
+    draw_text(
+        &format!("{}/{}: change  {}: back", bindings.describe(Action::MenuLeft), bindings.describe(Action::MenuRight), bindings.describe(Action::Back)),
+        -8.0,
+        248.0,
+        12.0,
+        WHITE,
+    );
+}
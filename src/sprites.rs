@@ -0,0 +1,165 @@
+use macroquad::prelude::*;
+use crate::block::Block;
+use crate::engine::Engine;
+use crate::engine::Event;
+use crate::layout::held_position;
+use crate::layout::queue_position;
+use crate::settings::GhostStyle;
+use crate::Game;
+use crate::COLORS;
+
+/// Where a block is drawn, in cells. It eases towards where the block really
+/// is every tick, so moves, spawns and holds slide rather than jump.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Motion {
+    position: Vec2,
+    /// `position` as of the tick before, so frames between ticks can draw the
+    /// block part of the way along.
+    last_position: Vec2,
+}
+
+impl Motion {
+    pub fn new(position: Vec2) -> Motion {
+        Motion {
+            position,
+            last_position: position,
+        }
+    }
+
+    /// Moves a tick's worth of the way to `target`.
+    pub fn ease(&mut self, target: Vec2) {
+        self.last_position = self.position;
+        self.position = self.position.lerp(target, 0.5);
+    }
+
+    /// Where to draw the block, `alpha` of the way from the last tick to the
+    /// latest one.
+    pub fn at(&self, alpha: f32) -> Vec2 {
+        self.last_position.lerp(self.position, alpha)
+    }
+}
+
+/// Where the active, upcoming and held blocks are drawn, following them as
+/// they move between the queue, the board and the held slot.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sprites {
+    pub block: Motion,
+    pub queue: Vec<Motion>,
+    pub held: Motion,
+}
+
+impl Sprites {
+    /// Everything where it is in `engine`, except the first block, which
+    /// comes in from the front of the queue.
+    pub fn new(engine: &Engine) -> Sprites {
+        let queue: Vec<Motion> = (0..engine.queue.len()).map(|i| Motion::new(queue_position(&engine.board, i))).collect();
+        Sprites {
+            block: queue[0],
+            queue,
+            held: Motion::new(held_position(&engine.board)),
+        }
+    }
+
+    /// Eases everything a tick towards where it is in `engine`.
+    pub fn ease(&mut self, engine: &Engine) {
+        self.block.ease(engine.block.position);
+        for (i, motion) in self.queue.iter_mut().enumerate() {
+            motion.ease(queue_position(&engine.board, i));
+        }
+        self.held.ease(held_position(&engine.board));
+    }
+
+    /// Moves sprites along with blocks changing places.
+    pub fn handle_event(&mut self, engine: &Engine, event: Event) {
+        match event {
+            Event::Hold => std::mem::swap(&mut self.block, &mut self.held),
+            Event::Spawned => {
+                self.block = self.queue.remove(0);
+                // The new last block appears in the last slot.
+                self.queue.push(Motion::new(queue_position(&engine.board, self.queue.len())));
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Draws `block` at `position`, `scale` times its usual size for previews.
+pub fn render_block(game: &Game, block: &Block, position: Vec2, scale: f32) {
+    let layout = game.layout();
+    for (y, row) in block.get_shape().iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell != 0 {
+                layout.draw_scaled_cell(
+                    game.block_texture.unwrap(),
+                    position + vec2(x as f32, y as f32) * scale,
+                    scale,
+                    COLORS[cell as usize],
+                );
+            }
+        }
+    }
+}
+
+/// Darkens the block as its lock timer runs out, `progress` going from 0 to
+/// 1.
+pub fn render_lock(game: &Game, block: &Block, position: Vec2, progress: f32) {
+    if progress <= 0.0 {
+        return;
+    }
+    let layout = game.layout();
+    for (y, row) in block.get_shape().iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell != 0 {
+                let screen = layout.screen_position(position + vec2(x as f32, y as f32));
+                draw_rectangle(
+                    screen.x,
+                    screen.y,
+                    layout.cell,
+                    layout.cell,
+                    Color {
+                        a: progress * 0.6,
+                        ..COLORS[0]
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Draws where the block would land, in column `x` and board row
+/// `landing_y`.
+pub fn render_ghost(game: &Game, block: &Block, x: f32, landing_y: f32, style: GhostStyle) {
+    let layout = game.layout();
+    for (cell_y, row) in block.get_shape().iter().enumerate() {
+        for (cell_x, &cell) in row.iter().enumerate() {
+            if cell == 0 {
+                continue;
+            }
+            let position = vec2(x + cell_x as f32, landing_y + cell_y as f32);
+            match style {
+                GhostStyle::Off => {},
+                GhostStyle::Faded => {
+                    layout.draw_cell(
+                        game.block_texture.unwrap(),
+                        position,
+                        Color {
+                            a: 0.3,
+                            ..COLORS[cell as usize]
+                        },
+                    );
+                },
+                GhostStyle::Outline => {
+                    let screen = layout.screen_position(position);
+                    draw_rectangle_lines(
+                        screen.x + 1.0,
+                        screen.y + 1.0,
+                        layout.cell - 2.0,
+                        layout.cell - 2.0,
+                        2.0,
+                        COLORS[cell as usize],
+                    );
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use macroquad::prelude::*;
+use crate::controls::Action;
+use crate::menu::play_menu_sound;
+use crate::menu::MenuEvent;
+use crate::util::format_time;
+use crate::Game;
+use crate::GameState;
+use crate::COLORS;
+
+pub fn update_summary(game: &mut Game) {
+    if game.is_pressed(Action::Confirm)
+    || game.is_pressed(Action::Back) {
+        play_menu_sound(game, MenuEvent::Back);
+        game.state = GameState::Menu;
+    }
+}
+
+/// How the session went, shown when leaving a mode that never ends on its own.
+pub fn render_summary(game: &Game) {
+    let engine = &game.engine;
+    let stats = &engine.stats;
+    let seconds = (engine.frames / 60.0) as f32;
+    let pieces_per_second = if seconds > 0.0 { stats.pieces as f32 / seconds } else { 0.0 };
+    let rows = [
+        ("Time", format_time(seconds)),
+        ("Score", engine.score.score.to_string()),
+        ("Lines", engine.score.lines.to_string()),
+        ("Pieces", stats.pieces.to_string()),
+        ("Pieces/sec", format!("{:.2}", pieces_per_second)),
+        ("Singles", stats.clears[0].to_string()),
+        ("Doubles", stats.clears[1].to_string()),
+        ("Triples", stats.clears[2].to_string()),
+        ("Tetrises", stats.clears[3].to_string()),
+        ("T-spins", stats.t_spins.to_string()),
+        ("Best combo", stats.max_combo.to_string()),
+        ("Holds", stats.holds.to_string()),
+        ("Compressions", stats.compressions.to_string()),
+    ];
+    draw_text("Session summary", 50.0, 24.0, 32.0, WHITE);
+    for (i, (label, value)) in rows.iter().enumerate() {
+        let y = 50.0 + i as f32 * 14.0;
+        draw_text(label, 60.0, y, 16.0, COLORS[2]);
+        draw_text(value, 180.0, y, 16.0, WHITE);
+    }
+    draw_text(
+        &format!("{}: back to the menu", game.settings.bindings.describe(Action::Confirm)),
+        -8.0,
+        248.0,
+        12.0,
+        WHITE,
+    );
+}
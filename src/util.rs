@@ -1,6 +1,12 @@
 use macroquad::prelude::*;
 use macroquad::audio;
 use macroquad::audio::Sound;
+use macroquad::miniquad::date;
+use macroquad::rand::RandGenerator;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
 
 pub fn clamp_range<T: std::cmp::PartialOrd>(min: T, val: T, max: T) -> T {
 	if val > max { max }
@@ -10,6 +16,57 @@ pub fn clamp_range<T: std::cmp::PartialOrd>(min: T, val: T, max: T) -> T {
 
 pub fn delta_time() -> f32 { get_frame_time() * 60.0 }
 
+pub fn seeded_rng(seed: u64) -> RandGenerator {
+	let rng = RandGenerator::new();
+	rng.srand(seed);
+	rng
+}
+
+pub fn random_seed() -> u64 {
+	(date::now() * 1000.0) as u64
+}
+
+/// Reads `--seed <number>` from the command line, for replaying a reported game.
+pub fn seed_from_args() -> Option<u64> {
+	let args: Vec<String> = std::env::args().collect();
+	args.iter()
+		.position(|arg| arg == "--seed")
+		.and_then(|i| args.get(i + 1))
+		.and_then(|seed| seed.parse().ok())
+}
+
+/// Formats seconds as m:ss.cc.
+pub fn format_time(seconds: f32) -> String {
+	let centiseconds = (seconds * 100.0).round() as u32;
+	format!("{}:{:02}.{:02}", centiseconds / 6000, centiseconds / 100 % 60, centiseconds % 100)
+}
+
+/// Formats whole seconds as m:ss.
+pub fn format_time_short(seconds: u32) -> String {
+	format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Formats seconds as m:ss.mmm, for races against the clock.
+pub fn format_time_precise(seconds: f32) -> String {
+	let milliseconds = (seconds * 1000.0).round() as u32;
+	format!("{}:{:02}.{:03}", milliseconds / 60000, milliseconds / 1000 % 60, milliseconds % 1000)
+}
+
+/// Today's date as YYYY-MM-DD (UTC).
+pub fn today() -> String {
+	// Days since 1970 to a calendar date, from Howard Hinnant's civil_from_days.
+	let days = (date::now() / 86400.0) as i64 + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days.rem_euclid(146097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
 pub fn get_file_path(path: String) -> String {
 	path
 }
@@ -20,4 +77,65 @@ pub async fn load_texture_file(file_path: String) -> Texture2D {
 
 pub async fn load_sound_file(file_path: String) -> Sound {
 	audio::load_sound(&get_file_path(file_path)).await.unwrap()
-}
\ No newline at end of file
+}
+
+/// Where files the game writes (settings, controls, scores) are kept.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+	dirs::config_dir().map(|dir| dir.join("rs-tris").join(file_name))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_config_file(file_name: &str) -> Option<String> {
+	fs::read_to_string(config_path(file_name)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_config_file(file_name: &str, contents: &str) -> Result<(), String> {
+	let path = config_path(file_name).ok_or("no config directory on this platform")?;
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+	}
+	fs::write(&path, contents).map_err(|error| error.to_string())
+}
+
+// On the web there's no file system, so files go in the browser's local
+// storage instead, through web/rs_tris_storage.js.
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+	fn rs_tris_storage_length(key: *const u8, key_length: usize) -> i32;
+	fn rs_tris_storage_read(key: *const u8, key_length: usize, buffer: *mut u8);
+	fn rs_tris_storage_write(key: *const u8, key_length: usize, value: *const u8, value_length: usize);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_config_file(file_name: &str) -> Option<String> {
+	let length = unsafe { rs_tris_storage_length(file_name.as_ptr(), file_name.len()) };
+	if length < 0 {
+		return None;
+	}
+	let mut buffer = vec![0; length as usize];
+	unsafe { rs_tris_storage_read(file_name.as_ptr(), file_name.len(), buffer.as_mut_ptr()) };
+	String::from_utf8(buffer).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_config_file(file_name: &str, contents: &str) -> Result<(), String> {
+	unsafe { rs_tris_storage_write(file_name.as_ptr(), file_name.len(), contents.as_ptr(), contents.len()) };
+	Ok(())
+}
+
+/// Splits `text` into lines of at most `max_chars`, breaking between words.
+pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+	let mut lines: Vec<String> = Vec::new();
+	for word in text.split_whitespace() {
+		match lines.last_mut() {
+			Some(line) if line.len() + word.len() < max_chars => {
+				line.push(' ');
+				line.push_str(word);
+			},
+			_ => lines.push(word.to_string()),
+		}
+	}
+	lines
+}
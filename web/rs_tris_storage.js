@@ -0,0 +1,23 @@
+// Lets the game keep its settings and save files in local storage.
+// Load this after gl.js and before the wasm file in the page.
+miniquad_add_plugin({
+    name: "rs_tris_storage",
+    version: 1,
+    register_plugin: function (importObject) {
+        function readString(pointer, length) {
+            return new TextDecoder().decode(new Uint8Array(wasm_memory.buffer, pointer, length));
+        }
+
+        importObject.env.rs_tris_storage_length = function (key, keyLength) {
+            var value = localStorage.getItem(readString(key, keyLength));
+            return value === null ? -1 : new TextEncoder().encode(value).length;
+        };
+        importObject.env.rs_tris_storage_read = function (key, keyLength, buffer) {
+            var value = new TextEncoder().encode(localStorage.getItem(readString(key, keyLength)) || "");
+            new Uint8Array(wasm_memory.buffer, buffer, value.length).set(value);
+        };
+        importObject.env.rs_tris_storage_write = function (key, keyLength, value, valueLength) {
+            localStorage.setItem(readString(key, keyLength), readString(value, valueLength));
+        };
+    },
+});